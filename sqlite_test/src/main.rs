@@ -1,4 +1,6 @@
 use rusqlite::{params, Connection, Result};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 #[derive(Debug)]
 struct SaleWithProduct {
@@ -68,9 +70,14 @@ fn print_db(conn: &Connection) -> Result<()> {
         })
     })? {
         if let Ok(item) = sale_with_product {
+            // Dates are stored as seconds since the Unix epoch, in UTC.
+            let date = OffsetDateTime::from_unix_timestamp(item.date)
+                .ok()
+                .and_then(|date| date.format(&Rfc3339).ok())
+                .unwrap_or_else(|| item.date.to_string());
             println!(
-                "At instant {}, {} {} of {} were sold.",
-                item.date, item.quantity, item.unit, item.name
+                "On {}, {} {} of {} were sold.",
+                date, item.quantity, item.unit, item.name
             );
         }
     }
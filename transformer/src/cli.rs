@@ -0,0 +1,125 @@
+use transformer::Error;
+
+/// Command line arguments following the subcommand name.
+pub struct Arguments {
+    options: Vec<(String, String)>,
+    switches: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Arguments {
+    /// `options` lists the `--name <value>` options the command takes, and
+    /// `switches` the `--name` flags that take no value. Any other is an error.
+    pub fn parse(
+        mut args: impl Iterator<Item = String>,
+        options: &[&str],
+        switches: &[&str],
+    ) -> Result<Self, Error> {
+        let mut arguments = Arguments {
+            options: Vec::new(),
+            switches: Vec::new(),
+            positional: Vec::new(),
+        };
+        let unknown = |name: &str| Error::Usage(format!("unknown option --{}", name));
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    arguments.positional.push(arg);
+                    continue;
+                }
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                if !options.contains(&name) {
+                    return Err(unknown(name));
+                }
+                arguments
+                    .options
+                    .push((name.to_string(), value.to_string()));
+            } else if switches.contains(&name) {
+                arguments.switches.push(name.to_string());
+            } else if options.contains(&name) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Error::MissingArgument(format!("value for --{}", name)))?;
+                arguments.options.push((name.to_string(), value));
+            } else {
+                return Err(unknown(name));
+            }
+        }
+        Ok(arguments)
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options(name).pop()
+    }
+
+    pub fn options<'a>(&'a self, name: &str) -> Vec<&'a str> {
+        self.options
+            .iter()
+            .filter(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    pub fn positionals(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn positional(&self, index: usize, what: &str) -> Result<&str, Error> {
+        self.positional
+            .get(index)
+            .map(|arg| arg.as_str())
+            .ok_or_else(|| Error::MissingArgument(what.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Arguments, Error> {
+        let args = args.iter().map(|arg| arg.to_string());
+        Arguments::parse(args, &["from", "header"], &["lenient"])
+    }
+
+    #[test]
+    fn reads_options_switches_and_positionals() {
+        let arguments = parse(&[
+            "--from",
+            "xml",
+            "in.xml",
+            "--lenient",
+            "--header=sale.id=ID",
+            "--header",
+            "product.id=PID",
+            "out.json",
+        ])
+        .unwrap();
+        assert_eq!(arguments.option("from"), Some("xml"));
+        assert_eq!(
+            arguments.options("header"),
+            ["sale.id=ID", "product.id=PID"]
+        );
+        assert!(arguments.switch("lenient"));
+        assert_eq!(arguments.positionals(), ["in.xml", "out.json"]);
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        for args in [
+            &["--form", "xml", "in.xml"][..],
+            &["--to=xml"],
+            &["--lenient=yes"],
+        ] {
+            let error = parse(args).err().unwrap();
+            assert!(error.is_usage(), "{:?}", args);
+            assert!(error.to_string().contains("unknown option --"), "{}", error);
+        }
+        assert!(matches!(parse(&["--from"]), Err(Error::MissingArgument(_))));
+    }
+}
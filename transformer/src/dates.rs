@@ -0,0 +1,333 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+use crate::model::{Field, Fields, Record};
+use crate::Error;
+
+// Integers this large are taken as milliseconds: as seconds they would be
+// past the year 5000, as milliseconds they are after March 1973.
+const MILLISECONDS_FROM: i64 = 100_000_000_000;
+
+/// Reads a date as seconds since the Unix epoch. It may be written as an
+/// integer of seconds or milliseconds, as RFC 3339, or as ISO 8601 with or
+/// without a time and offset, a missing offset meaning UTC.
+pub fn parse_date(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    if let Ok(epoch) = text.parse::<i64>() {
+        return Ok(from_epoch_number(epoch));
+    }
+    if let Ok(date_time) = OffsetDateTime::parse(text, &Rfc3339) {
+        return Ok(date_time.unix_timestamp());
+    }
+    if let Ok(date_time) = OffsetDateTime::parse(text, &Iso8601::DEFAULT) {
+        return Ok(date_time.unix_timestamp());
+    }
+    if let Ok(date_time) = PrimitiveDateTime::parse(text, &Iso8601::DEFAULT) {
+        return Ok(date_time.assume_utc().unix_timestamp());
+    }
+    if let Ok(date) = Date::parse(text, &Iso8601::DEFAULT) {
+        return Ok(date.midnight().assume_utc().unix_timestamp());
+    }
+    Err("expected epoch seconds or milliseconds, or an ISO 8601 date".to_string())
+}
+
+fn from_epoch_number(epoch: i64) -> i64 {
+    if epoch.abs() >= MILLISECONDS_FROM {
+        epoch.div_euclid(1000)
+    } else {
+        epoch
+    }
+}
+
+/// Deserializes a date written in any of the ways `parse_date` accepts.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    struct DateVisitor;
+
+    impl Visitor<'_> for DateVisitor {
+        type Value = i64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("epoch seconds or milliseconds, or an ISO 8601 date")
+        }
+
+        fn visit_i64<E: de::Error>(self, epoch: i64) -> Result<i64, E> {
+            Ok(from_epoch_number(epoch))
+        }
+
+        fn visit_u64<E: de::Error>(self, epoch: u64) -> Result<i64, E> {
+            i64::try_from(epoch)
+                .map(from_epoch_number)
+                .map_err(|_| E::custom("date out of range"))
+        }
+
+        fn visit_str<E: de::Error>(self, text: &str) -> Result<i64, E> {
+            parse_date(text).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_any(DateVisitor)
+}
+
+/// A fixed offset from UTC, written `UTC`, `Z` or like `+02:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeZone(pub UtcOffset);
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone(UtcOffset::UTC)
+    }
+}
+
+impl FromStr for TimeZone {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time zone {:?}, expected UTC or like +02:00", text);
+        if text.eq_ignore_ascii_case("utc") || text == "Z" {
+            return Ok(TimeZone(UtcOffset::UTC));
+        }
+        let (sign, rest) = match text.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        // The sign comes once, before the hours.
+        if !rest.chars().all(|c| c.is_ascii_digit() || c == ':') {
+            return Err(invalid());
+        }
+        let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+        let hours: i8 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i8 = minutes.parse().map_err(|_| invalid())?;
+        UtcOffset::from_hms(sign * hours, sign * minutes, 0)
+            .map(TimeZone)
+            .map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_utc() {
+            return f.write_str("UTC");
+        }
+        let (hours, minutes, _) = self.0.as_hms();
+        let sign = if self.0.is_negative() { '-' } else { '+' };
+        write!(f, "{}{:02}:{:02}", sign, hours.abs(), minutes.abs())
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeZone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for TimeZone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// How dates are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateFormat {
+    /// Seconds since the Unix epoch, as stored.
+    #[default]
+    Epoch,
+    /// RFC 3339 in the display time zone, such as `2009-02-13T23:31:30Z`.
+    Iso,
+}
+
+impl FromStr for DateFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "epoch" => Ok(DateFormat::Epoch),
+            "iso" => Ok(DateFormat::Iso),
+            _ => Err(Error::Usage(format!(
+                "unknown date format {:?}, expected epoch or iso",
+                name
+            ))),
+        }
+    }
+}
+
+/// The `[dates]` section of the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DateOptions {
+    pub format: DateFormat,
+    pub time_zone: TimeZone,
+    /// Only sales from this date on are read.
+    #[serde(deserialize_with = "deserialize_optional")]
+    pub since: Option<i64>,
+    /// Only sales before this date are read.
+    #[serde(deserialize_with = "deserialize_optional")]
+    pub until: Option<i64>,
+}
+
+fn deserialize_optional<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i64>, D::Error> {
+    deserialize(deserializer).map(Some)
+}
+
+impl DateOptions {
+    /// Writes an epoch in the display time zone, whatever the format.
+    pub fn display(&self, epoch: i64) -> String {
+        match OffsetDateTime::from_unix_timestamp(epoch) {
+            Ok(date_time) => date_time
+                .to_offset(self.time_zone.0)
+                .format(&Rfc3339)
+                .unwrap_or_else(|_| epoch.to_string()),
+            Err(_) => epoch.to_string(),
+        }
+    }
+
+    /// The calendar date of an epoch in the display time zone.
+    pub fn local_date(&self, epoch: i64) -> Option<Date> {
+        OffsetDateTime::from_unix_timestamp(epoch)
+            .ok()
+            .map(|date_time| date_time.to_offset(self.time_zone.0).date())
+    }
+
+    pub fn format(&self, epoch: i64) -> String {
+        match self.format {
+            DateFormat::Epoch => epoch.to_string(),
+            DateFormat::Iso => self.display(epoch),
+        }
+    }
+
+    /// The text of a field, with dates written in the chosen format.
+    pub fn field_text<T: Fields>(&self, field: &Field<T>, record: &T) -> String {
+        let text = (field.get)(record);
+        if self.format == DateFormat::Epoch || !T::DATE_FIELDS.contains(&field.name) {
+            return text;
+        }
+        match text.parse() {
+            Ok(epoch) => self.display(epoch),
+            Err(_) => text,
+        }
+    }
+
+    pub fn has_range(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    pub fn in_range(&self, epoch: i64) -> bool {
+        self.since.is_none_or(|since| epoch >= since)
+            && self.until.is_none_or(|until| epoch < until)
+    }
+}
+
+/// Leaves out the sales of `records` dated outside of the range of `dates`.
+pub fn filter_dates(
+    records: impl Iterator<Item = Result<Record, Error>>,
+    dates: DateOptions,
+) -> impl Iterator<Item = Result<Record, Error>> {
+    records.filter(move |record| match record {
+        Ok(Record::Sale(sale)) => dates.in_range(sale.date),
+        _ => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Product, Sale};
+
+    // 2009-02-13T23:31:30Z
+    const EPOCH: i64 = 1_234_567_890;
+
+    #[test]
+    fn reads_iso_8601_with_and_without_an_offset() {
+        assert_eq!(parse_date("2009-02-13T23:31:30Z"), Ok(EPOCH));
+        assert_eq!(parse_date("2009-02-14T01:31:30+02:00"), Ok(EPOCH));
+        assert_eq!(parse_date("2009-02-13T18:31:30-05:00"), Ok(EPOCH));
+        assert_eq!(parse_date("2009-02-13T23:31:30"), Ok(EPOCH));
+        assert_eq!(
+            parse_date("2009-02-13"),
+            Ok(EPOCH - 23 * 3600 - 31 * 60 - 30)
+        );
+        assert!(parse_date("13/02/2009").is_err());
+    }
+
+    #[test]
+    fn tells_epoch_seconds_from_milliseconds() {
+        assert_eq!(parse_date("1234567890"), Ok(EPOCH));
+        assert_eq!(parse_date("1234567890123"), Ok(EPOCH));
+        assert_eq!(parse_date("99999999999"), Ok(99_999_999_999));
+        assert_eq!(parse_date("100000000000"), Ok(100_000_000));
+        assert_eq!(parse_date("-1234567890123"), Ok(-EPOCH - 1));
+        let sale: Sale = serde_json::from_str(
+            r#"{"id": "s1", "product_id": 1, "date": 1234567890123, "quantity": 1.0, "unit": "kg"}"#,
+        )
+        .unwrap();
+        assert_eq!(sale.date, EPOCH);
+    }
+
+    #[test]
+    fn takes_utc_and_fixed_offsets_but_not_named_zones() {
+        assert_eq!("UTC".parse(), Ok(TimeZone::default()));
+        assert_eq!("Z".parse(), Ok(TimeZone::default()));
+        let zone: TimeZone = "+05:30".parse().unwrap();
+        assert_eq!(zone.to_string(), "+05:30");
+        assert_eq!("-3".parse::<TimeZone>().unwrap().to_string(), "-03:00");
+        for name in [
+            "Europe/Paris",
+            "CET",
+            "Mars",
+            "+02:60",
+            "",
+            "+-3",
+            "-+03:00",
+            "+02:-30",
+        ] {
+            assert!(name.parse::<TimeZone>().is_err(), "{:?}", name);
+        }
+        let options = DateOptions {
+            time_zone: zone,
+            ..Default::default()
+        };
+        assert_eq!(options.display(EPOCH), "2009-02-14T05:01:30+05:30");
+        assert_eq!(options.local_date(EPOCH).unwrap().day(), 14);
+    }
+
+    #[test]
+    fn keeps_sales_from_since_until_before_until() {
+        let sale = |id: &str, date| {
+            Ok(Record::Sale(Sale {
+                id: id.to_string(),
+                date,
+                ..Default::default()
+            }))
+        };
+        let records = vec![
+            Ok(Record::Product(Product::default())),
+            sale("before", EPOCH - 1),
+            sale("since", EPOCH),
+            sale("until", EPOCH + 10),
+        ];
+        let dates = DateOptions {
+            since: Some(EPOCH),
+            until: Some(EPOCH + 10),
+            ..Default::default()
+        };
+        let kept: Vec<Record> = filter_dates(records.into_iter(), dates)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(kept.len(), 2);
+        assert!(matches!(&kept[0], Record::Product(_)));
+        assert!(matches!(&kept[1], Record::Sale(sale) if sale.id == "since"));
+        assert!(DateOptions::default().in_range(i64::MIN));
+    }
+}
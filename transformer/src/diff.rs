@@ -0,0 +1,408 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::edit::remove_key;
+use crate::model::{differing_fields, Fields, Product, Sale, SalesAndProducts};
+use crate::Error;
+
+/// How a record changed between the older and the newer dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    Added(T),
+    /// `index` is the position of the record in the older dataset.
+    Removed {
+        index: usize,
+        record: T,
+    },
+    Modified {
+        index: usize,
+        old: T,
+        new: T,
+        fields: Vec<&'static str>,
+    },
+}
+
+/// The changes from an older to a newer dataset, matching records by id.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diff {
+    pub products: Vec<Change<Product>>,
+    pub sales: Vec<Change<Sale>>,
+}
+
+/// An operation of a JSON patch, as in RFC 6902.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add {
+        path: String,
+        value: Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: Value,
+    },
+    /// Fails the patch unless the value at `path` is `value`.
+    Test {
+        path: String,
+        value: Value,
+    },
+}
+
+/// Compares two datasets. Only the first of records sharing an id is compared.
+pub fn diff(old: &SalesAndProducts, new: &SalesAndProducts) -> Diff {
+    Diff {
+        products: diff_records(&old.products, &new.products),
+        sales: diff_records(&old.sales, &new.sales),
+    }
+}
+
+fn id<T: Fields>(record: &T) -> String {
+    T::field("id").map_or_else(String::new, |field| (field.get)(record))
+}
+
+fn diff_records<T: Fields + Clone>(old: &[T], new: &[T]) -> Vec<Change<T>> {
+    let mut new_by_id = HashMap::new();
+    for record in new {
+        new_by_id.entry(id(record)).or_insert(record);
+    }
+    let mut old_ids = HashMap::new();
+    let mut changes = Vec::new();
+    for (index, record) in old.iter().enumerate() {
+        let record_id = id(record);
+        if old_ids.insert(record_id.clone(), index).is_some() {
+            continue;
+        }
+        match new_by_id.get(&record_id) {
+            None => changes.push(Change::Removed {
+                index,
+                record: record.clone(),
+            }),
+            Some(new_record) => {
+                let fields = differing_fields(record, *new_record);
+                if !fields.is_empty() {
+                    changes.push(Change::Modified {
+                        index,
+                        old: record.clone(),
+                        new: (*new_record).clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    for record in new {
+        if let Entry::Vacant(entry) = old_ids.entry(id(record)) {
+            entry.insert(old.len());
+            changes.push(Change::Added(record.clone()));
+        }
+    }
+    changes
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty() && self.sales.is_empty()
+    }
+
+    /// Counts of added, removed and modified records.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        let kinds = self
+            .products
+            .iter()
+            .map(change_kind)
+            .chain(self.sales.iter().map(change_kind));
+        for kind in kinds {
+            match kind {
+                '+' => counts.0 += 1,
+                '-' => counts.1 += 1,
+                _ => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// One line per change: `+` for an added record, `-` for a removed one
+    /// and `~` for a modified one, followed by the fields that changed.
+    pub fn write_text<W: Write>(&self, mut sink: W) -> Result<(), Error> {
+        for line in self.products.iter().map(describe) {
+            writeln!(sink, "{}", line?)?;
+        }
+        for line in self.sales.iter().map(describe) {
+            writeln!(sink, "{}", line?)?;
+        }
+        Ok(())
+    }
+
+    /// The JSON patch turning the older dataset, as written by `convert`
+    /// to JSON, into the newer one.
+    pub fn patch(&self) -> Result<Vec<PatchOperation>, Error> {
+        let mut operations = patch_operations("products", &self.products)?;
+        operations.extend(patch_operations("sales", &self.sales)?);
+        Ok(operations)
+    }
+}
+
+fn change_kind<T>(change: &Change<T>) -> char {
+    match change {
+        Change::Added(_) => '+',
+        Change::Removed { .. } => '-',
+        Change::Modified { .. } => '~',
+    }
+}
+
+fn describe<T: Fields + Serialize>(change: &Change<T>) -> Result<String, Error> {
+    let record = |record: &T| format!("{} {}", T::NAME, id(record));
+    Ok(match change {
+        Change::Added(added) => format!("+ {}: {}", record(added), fields_text(added)?),
+        Change::Removed {
+            record: removed, ..
+        } => {
+            format!("- {}: {}", record(removed), fields_text(removed)?)
+        }
+        Change::Modified {
+            old, new, fields, ..
+        } => {
+            let (old_value, new_value) = (serde_json::to_value(old)?, serde_json::to_value(new)?);
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| format!("{} {} -> {}", field, old_value[field], new_value[field]))
+                .collect();
+            format!("~ {}: {}", record(old), fields.join(", "))
+        }
+    })
+}
+
+fn fields_text<T: Fields + Serialize>(record: &T) -> Result<String, Error> {
+    let value = serde_json::to_value(record)?;
+    let fields: Vec<String> = T::FIELDS
+        .iter()
+        .filter(|field| field.name != "id")
+        .map(|field| format!("{} {}", field.name, value[field.name]))
+        .collect();
+    Ok(fields.join(", "))
+}
+
+// Replacements and removals refer to positions in the older array, so they
+// come first, removals from the end, and additions are appended last. The id
+// of each record replaced or removed is tested first, so that the patch is
+// not applied to a file other than the one it was made from.
+fn patch_operations<T: Serialize>(
+    array: &str,
+    changes: &[Change<T>],
+) -> Result<Vec<PatchOperation>, Error> {
+    let mut tests = Vec::new();
+    let mut replacements = Vec::new();
+    let mut removals = Vec::new();
+    let mut additions = Vec::new();
+    for change in changes {
+        match change {
+            Change::Added(record) => additions.push(PatchOperation::Add {
+                path: format!("/{}/-", array),
+                value: serde_json::to_value(record)?,
+            }),
+            Change::Removed { index, record } => {
+                tests.push(test_id(array, *index, record)?);
+                removals.push(*index);
+            }
+            Change::Modified {
+                index,
+                old,
+                new,
+                fields,
+            } => {
+                tests.push(test_id(array, *index, old)?);
+                let value = serde_json::to_value(new)?;
+                for field in fields {
+                    replacements.push(PatchOperation::Replace {
+                        path: format!("/{}/{}/{}", array, index, field),
+                        value: value[field].clone(),
+                    });
+                }
+            }
+        }
+    }
+    removals.sort_by(|a, b| b.cmp(a));
+    let removals = removals.into_iter().map(|index| PatchOperation::Remove {
+        path: format!("/{}/{}", array, index),
+    });
+    Ok(tests
+        .into_iter()
+        .chain(replacements)
+        .chain(removals)
+        .chain(additions)
+        .collect())
+}
+
+fn test_id<T: Serialize>(array: &str, index: usize, record: &T) -> Result<PatchOperation, Error> {
+    Ok(PatchOperation::Test {
+        path: format!("/{}/{}/id", array, index),
+        value: serde_json::to_value(record)?["id"].clone(),
+    })
+}
+
+/// Applies the operations of a JSON patch in order, stopping at the first
+/// that cannot be. Returns the number of operations applied.
+pub fn apply_patch(document: &mut Value, operations: &[PatchOperation]) -> Result<usize, Error> {
+    for operation in operations {
+        apply_operation(document, operation)?;
+    }
+    Ok(operations.len())
+}
+
+fn apply_operation(document: &mut Value, operation: &PatchOperation) -> Result<(), Error> {
+    let path = match operation {
+        PatchOperation::Add { path, .. }
+        | PatchOperation::Remove { path }
+        | PatchOperation::Replace { path, .. }
+        | PatchOperation::Test { path, .. } => path,
+    };
+    let fail = |reason: &str| Error::Edit {
+        path: path.clone(),
+        reason: reason.to_string(),
+    };
+    if let PatchOperation::Test { value, .. } = operation {
+        return match document.pointer(path) {
+            Some(found) if found == value => Ok(()),
+            Some(_) => Err(fail("differs from the file the patch was made from")),
+            None => Err(fail("no such value")),
+        };
+    }
+    let (parent, token) = path
+        .rsplit_once('/')
+        .ok_or_else(|| fail("a JSON pointer starts with /"))?;
+    let token = token.replace("~1", "/").replace("~0", "~");
+    let parent = document
+        .pointer_mut(parent)
+        .ok_or_else(|| fail("no such value"))?;
+    match parent {
+        Value::Array(items) => {
+            let index = match token.as_str() {
+                "-" => items.len(),
+                _ => token
+                    .parse::<usize>()
+                    .map_err(|_| fail("not an array index"))?,
+            };
+            match operation {
+                PatchOperation::Add { value, .. } if index <= items.len() => {
+                    items.insert(index, value.clone())
+                }
+                PatchOperation::Remove { .. } if index < items.len() => {
+                    items.remove(index);
+                }
+                PatchOperation::Replace { value, .. } if index < items.len() => {
+                    items[index] = value.clone()
+                }
+                _ => return Err(fail("index out of bounds")),
+            }
+        }
+        Value::Object(object) => match operation {
+            PatchOperation::Add { value, .. } => {
+                object.insert(token, value.clone());
+            }
+            PatchOperation::Remove { .. } => {
+                remove_key(object, &token).ok_or_else(|| fail("no such key"))?;
+            }
+            PatchOperation::Replace { value, .. } => {
+                let field = object.get_mut(&token).ok_or_else(|| fail("no such key"))?;
+                *field = value.clone();
+            }
+            PatchOperation::Test { .. } => unreachable!("tests are applied above"),
+        },
+        _ => return Err(fail("not inside an array or object")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(id: i32, name: &str) -> Product {
+        Product {
+            id,
+            category: "fruit".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn sale(id: &str, quantity: f64) -> Sale {
+        Sale {
+            id: id.to_string(),
+            product_id: 1,
+            date: 1_234_567_890,
+            quantity,
+            unit: "kg".to_string(),
+        }
+    }
+
+    fn old() -> SalesAndProducts {
+        SalesAndProducts {
+            products: vec![product(1, "apple"), product(2, "pear"), product(3, "plum")],
+            sales: vec![sale("a", 1.0), sale("b", 2.0), sale("c", 3.0)],
+        }
+    }
+
+    fn new() -> SalesAndProducts {
+        SalesAndProducts {
+            products: vec![
+                product(3, "red plum"),
+                product(1, "apple"),
+                product(4, "fig"),
+            ],
+            sales: vec![sale("c", 3.5), sale("d", 4.0)],
+        }
+    }
+
+    // The records of a document, in the order of their ids.
+    fn sorted(document: Value) -> SalesAndProducts {
+        let mut sales_and_products: SalesAndProducts = serde_json::from_value(document).unwrap();
+        sales_and_products
+            .products
+            .sort_by_key(|product| product.id);
+        sales_and_products.sales.sort_by(|a, b| a.id.cmp(&b.id));
+        sales_and_products
+    }
+
+    #[test]
+    fn patch_turns_old_into_new() {
+        let (old, new) = (old(), new());
+        let operations = diff(&old, &new).patch().unwrap();
+        let mut document = serde_json::to_value(&old).unwrap();
+        assert_eq!(
+            apply_patch(&mut document, &operations).unwrap(),
+            operations.len()
+        );
+        assert_eq!(
+            sorted(document),
+            sorted(serde_json::to_value(&new).unwrap())
+        );
+        assert!(diff(&old, &old).patch().unwrap().is_empty());
+    }
+
+    #[test]
+    fn patch_fails_on_another_base() {
+        let operations = diff(&old(), &new()).patch().unwrap();
+        let mut other = old();
+        other.products.swap(0, 1);
+        let mut document = serde_json::to_value(&other).unwrap();
+        let error = apply_patch(&mut document, &operations).unwrap_err();
+        assert!(
+            matches!(&error, Error::Edit { path, .. } if path == "/products/1/id"),
+            "{}",
+            error
+        );
+        let mut shorter = old();
+        shorter.sales.truncate(1);
+        let mut document = serde_json::to_value(&shorter).unwrap();
+        assert!(apply_patch(&mut document, &operations).is_err());
+    }
+}
@@ -0,0 +1,787 @@
+//! Bulk edits of dynamic documents, written as statements such as
+//!
+//! ```text
+//! sales[*].quantity *= 1000
+//! sales[?unit == "g"].unit = "kg"
+//! delete products[?category == "obsolete"]
+//! rename sales[*].date to sold_at
+//! ```
+//!
+//! Paths start at the document root and select values with `.key`,
+//! `["key"]`, `[index]` (negative from the end), `[*]` or `.*` for every
+//! element, and `[?path op value]` for the array elements whose `path`
+//! compares to a JSON value with `==`, `!=`, `<`, `<=`, `>` or `>=`.
+//! `[?path]` alone keeps the elements where `path` is present and not
+//! `null` or `false`.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use serde_json::{Map, Number, Value};
+
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Filter(Filter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    path: Vec<String>,
+    comparison: Option<(Comparison, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operation {
+    Set(Value),
+    Arithmetic(Arithmetic, Number),
+    Delete,
+    Rename(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Statement {
+    path: Vec<Step>,
+    operation: Operation,
+}
+
+/// Statements separated by `;` or newlines, applied in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    statements: Vec<Statement>,
+}
+
+impl FromStr for Edit {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            text: expression,
+            position: 0,
+        };
+        let mut statements = Vec::new();
+        loop {
+            while parser.peek().is_some_and(|c| c == ';' || c.is_whitespace()) {
+                parser.bump();
+            }
+            if parser.peek().is_none() {
+                break;
+            }
+            statements.push(parser.statement()?);
+            parser.skip_spaces();
+            match parser.peek() {
+                None | Some(';') | Some('\n') => {}
+                Some(c) => return Err(parser.error(format!("expected `;`, found `{}`", c))),
+            }
+        }
+        if statements.is_empty() {
+            return Err(parser.error("nothing to do".to_string()));
+        }
+        Ok(Edit { statements })
+    }
+}
+
+impl Edit {
+    /// Applies every statement in turn, returning how many values were
+    /// set, changed, deleted or renamed.
+    pub fn apply(&self, document: &mut Value) -> Result<usize, Error> {
+        let mut changed = 0;
+        for statement in &self.statements {
+            changed += visit(
+                document,
+                &statement.path,
+                String::new(),
+                &mut |parent, step, path| statement.operation.apply(parent, step, path),
+            )?;
+        }
+        Ok(changed)
+    }
+}
+
+type Last<'a> = dyn FnMut(&mut Value, &Step, &str) -> Result<usize, Error> + 'a;
+
+// Calls `last` with each value selected by all steps but the last one.
+fn visit(value: &mut Value, steps: &[Step], path: String, last: &mut Last) -> Result<usize, Error> {
+    let (step, rest) = steps.split_first().expect("paths have at least one step");
+    if rest.is_empty() {
+        return last(value, step, &path);
+    }
+    let mut changed = 0;
+    for (child_path, child) in children(value, step, &path) {
+        changed += visit(child, rest, child_path, last)?;
+    }
+    Ok(changed)
+}
+
+fn children<'a>(value: &'a mut Value, step: &Step, path: &str) -> Vec<(String, &'a mut Value)> {
+    match (step, value) {
+        (Step::Key(key), Value::Object(map)) => map
+            .get_mut(key)
+            .map(|child| (child_path(path, key), child))
+            .into_iter()
+            .collect(),
+        (Step::Index(index), Value::Array(array)) => match resolve(*index, array.len()) {
+            Some(index) => vec![(format!("{}[{}]", path, index), &mut array[index])],
+            None => Vec::new(),
+        },
+        (Step::Wildcard, Value::Array(array)) => array
+            .iter_mut()
+            .enumerate()
+            .map(|(index, child)| (format!("{}[{}]", path, index), child))
+            .collect(),
+        (Step::Wildcard, Value::Object(map)) => map
+            .iter_mut()
+            .map(|(key, child)| (child_path(path, key), child))
+            .collect(),
+        (Step::Filter(filter), Value::Array(array)) => array
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, child)| filter.matches(child))
+            .map(|(index, child)| (format!("{}[{}]", path, index), child))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn resolve(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    usize::try_from(index).ok().filter(|index| *index < len)
+}
+
+impl Operation {
+    fn apply(&self, parent: &mut Value, step: &Step, path: &str) -> Result<usize, Error> {
+        match self {
+            Operation::Set(value) => {
+                // Setting a key adds it when missing.
+                if let (Step::Key(key), Value::Object(map)) = (step, &mut *parent) {
+                    map.insert(key.clone(), value.clone());
+                    return Ok(1);
+                }
+                let targets = children(parent, step, path);
+                let changed = targets.len();
+                for (_, target) in targets {
+                    *target = value.clone();
+                }
+                Ok(changed)
+            }
+            Operation::Arithmetic(arithmetic, operand) => {
+                let mut changed = 0;
+                for (path, target) in children(parent, step, path) {
+                    *target = arithmetic
+                        .apply(target, operand)
+                        .map_err(|reason| Error::Edit { path, reason })?;
+                    changed += 1;
+                }
+                Ok(changed)
+            }
+            Operation::Delete => Ok(delete(parent, step)),
+            Operation::Rename(name) => match (step, parent) {
+                // The key keeps its place, replacing any already called `name`.
+                (Step::Key(key), Value::Object(map)) if map.contains_key(key) => {
+                    *map = std::mem::take(map)
+                        .into_iter()
+                        .filter(|(other, _)| other != name || other == key)
+                        .map(|(other, value)| {
+                            if other == *key {
+                                (name.clone(), value)
+                            } else {
+                                (other, value)
+                            }
+                        })
+                        .collect();
+                    Ok(1)
+                }
+                _ => Ok(0),
+            },
+        }
+    }
+}
+
+fn delete(parent: &mut Value, step: &Step) -> usize {
+    match (step, parent) {
+        (Step::Key(key), Value::Object(map)) => usize::from(remove_key(map, key).is_some()),
+        (Step::Index(index), Value::Array(array)) => match resolve(*index, array.len()) {
+            Some(index) => {
+                array.remove(index);
+                1
+            }
+            None => 0,
+        },
+        (Step::Wildcard, Value::Array(array)) => std::mem::take(array).len(),
+        (Step::Wildcard, Value::Object(map)) => std::mem::take(map).len(),
+        (Step::Filter(filter), Value::Array(array)) => {
+            let before = array.len();
+            array.retain(|element| !filter.matches(element));
+            before - array.len()
+        }
+        _ => 0,
+    }
+}
+
+/// Removes a key, leaving the others in their order, which `Map::remove`
+/// does not.
+pub fn remove_key(map: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    let value = std::mem::take(map.get_mut(key)?);
+    map.retain(|other, _| other != key);
+    Some(value)
+}
+
+impl Arithmetic {
+    fn symbol(self) -> &'static str {
+        match self {
+            Arithmetic::Add => "+",
+            Arithmetic::Subtract => "-",
+            Arithmetic::Multiply => "*",
+            Arithmetic::Divide => "/",
+        }
+    }
+
+    // Integers stay integers as long as the result is exact.
+    fn apply(self, target: &Value, operand: &Number) -> Result<Value, String> {
+        let number = match target {
+            Value::Number(number) => number,
+            other => return Err(format!("{} is not a number", other)),
+        };
+        if let (Some(a), Some(b)) = (number.as_i64(), operand.as_i64()) {
+            let exact = match self {
+                Arithmetic::Add => a.checked_add(b),
+                Arithmetic::Subtract => a.checked_sub(b),
+                Arithmetic::Multiply => a.checked_mul(b),
+                Arithmetic::Divide => a.checked_rem(b).filter(|rem| *rem == 0).map(|_| a / b),
+            };
+            if let Some(result) = exact {
+                return Ok(result.into());
+            }
+        }
+        let (a, b) = (
+            number.as_f64().unwrap_or(f64::NAN),
+            operand.as_f64().unwrap_or(f64::NAN),
+        );
+        let result = match self {
+            Arithmetic::Add => a + b,
+            Arithmetic::Subtract => a - b,
+            Arithmetic::Multiply => a * b,
+            Arithmetic::Divide => a / b,
+        };
+        Number::from_f64(result)
+            .map(Value::Number)
+            .ok_or_else(|| format!("{} {} {} is not a finite number", a, self.symbol(), b))
+    }
+}
+
+impl Filter {
+    fn matches(&self, element: &Value) -> bool {
+        let found = self
+            .path
+            .iter()
+            .try_fold(element, |value, key| value.get(key));
+        match (&self.comparison, found) {
+            (None, found) => !matches!(found, None | Some(Value::Null) | Some(Value::Bool(false))),
+            (Some(_), None) => false,
+            (Some((comparison, expected)), Some(found)) => comparison.holds(found, expected),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, found: &Value, expected: &Value) -> bool {
+        let ordering = match (found, expected) {
+            (Value::Number(a), Value::Number(b)) => a
+                .as_f64()
+                .zip(b.as_f64())
+                .and_then(|(a, b)| a.partial_cmp(&b)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (a, b) => (a == b).then_some(Ordering::Equal),
+        };
+        match self {
+            Comparison::Equal => ordering == Some(Ordering::Equal),
+            Comparison::NotEqual => ordering != Some(Ordering::Equal),
+            Comparison::Less => ordering == Some(Ordering::Less),
+            Comparison::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Comparison::Greater => ordering == Some(Ordering::Greater),
+            Comparison::GreaterOrEqual => {
+                matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    // Byte offset of the next character.
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: String) -> Error {
+        Error::Expression {
+            column: self.text[..self.position].chars().count() + 1,
+            message,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", token)))
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace() && c != '\n') {
+            self.bump();
+        }
+    }
+
+    // A bare key: letters, digits, `_` and `-`, unless the `-` starts `-=`.
+    fn word(&mut self) -> Option<&'a str> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            let is_key =
+                c.is_alphanumeric() || c == '_' || (c == '-' && !self.rest().starts_with("-="));
+            if !is_key {
+                break;
+            }
+            self.bump();
+        }
+        Some(&self.text[start..self.position]).filter(|word| !word.is_empty())
+    }
+
+    // A keyword is only taken as one when followed by a space or the end.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let rest = self.rest();
+        let after = &rest[keyword.len().min(rest.len())..];
+        let ends = after.is_empty() || after.starts_with(|c: char| c.is_whitespace());
+        ends && rest.starts_with(keyword) && self.eat(keyword)
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", keyword)))
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        if self.keyword("delete") {
+            self.skip_spaces();
+            let path = self.path()?;
+            return Ok(Statement {
+                path,
+                operation: Operation::Delete,
+            });
+        }
+        if self.keyword("rename") {
+            self.skip_spaces();
+            let path = self.path()?;
+            if !matches!(path.last(), Some(Step::Key(_))) {
+                return Err(self.error("only keys can be renamed".to_string()));
+            }
+            self.expect_keyword("to")?;
+            self.skip_spaces();
+            let name = match self.peek() {
+                Some('"') => self.string()?,
+                _ => match self.word() {
+                    Some(word) => word.to_string(),
+                    None => return Err(self.error("expected the new key".to_string())),
+                },
+            };
+            return Ok(Statement {
+                path,
+                operation: Operation::Rename(name),
+            });
+        }
+        let path = self.path()?;
+        self.skip_spaces();
+        let arithmetic = [
+            ("+=", Arithmetic::Add),
+            ("-=", Arithmetic::Subtract),
+            ("*=", Arithmetic::Multiply),
+            ("/=", Arithmetic::Divide),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token));
+        if arithmetic.is_none() && !self.eat("=") {
+            return Err(self.error("expected `=`, `+=`, `-=`, `*=` or `/=`".to_string()));
+        }
+        let start = self.position;
+        let value = self.literal(&[';', '\n'])?;
+        let operation = match (arithmetic, value) {
+            (None, value) => Operation::Set(value),
+            (Some((_, arithmetic)), Value::Number(operand)) => {
+                Operation::Arithmetic(arithmetic, operand)
+            }
+            (Some((token, _)), _) => {
+                self.position = start;
+                return Err(self.error(format!("`{}` takes a number", token)));
+            }
+        };
+        Ok(Statement { path, operation })
+    }
+
+    fn path(&mut self) -> Result<Vec<Step>, Error> {
+        let mut steps = Vec::new();
+        if self.eat("$") {
+            self.eat(".");
+        }
+        loop {
+            let step = if self.eat("[") {
+                self.bracket()?
+            } else if steps.is_empty() || self.eat(".") {
+                if self.eat("*") {
+                    Step::Wildcard
+                } else {
+                    match self.word() {
+                        Some(word) => Step::Key(word.to_string()),
+                        None => return Err(self.error("expected a key".to_string())),
+                    }
+                }
+            } else {
+                break;
+            };
+            steps.push(step);
+        }
+        Ok(steps)
+    }
+
+    fn bracket(&mut self) -> Result<Step, Error> {
+        self.skip_spaces();
+        let step = match self.peek() {
+            Some('*') => {
+                self.bump();
+                Step::Wildcard
+            }
+            Some('?') => {
+                self.bump();
+                Step::Filter(self.filter()?)
+            }
+            Some('"') => Step::Key(self.string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.position;
+                self.bump();
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.bump();
+                }
+                let index = self.text[start..self.position].parse().map_err(|_| {
+                    self.position = start;
+                    self.error("invalid index".to_string())
+                })?;
+                Step::Index(index)
+            }
+            _ => return Err(self.error("expected `*`, `?`, an index or a key".to_string())),
+        };
+        self.expect("]")?;
+        Ok(step)
+    }
+
+    fn filter(&mut self) -> Result<Filter, Error> {
+        self.skip_spaces();
+        if self.eat("@") {
+            self.eat(".");
+        }
+        let mut path = Vec::new();
+        loop {
+            let key = match self.peek() {
+                Some('"') => self.string()?,
+                _ => match self.word() {
+                    Some(word) => word.to_string(),
+                    None => return Err(self.error("expected a key".to_string())),
+                },
+            };
+            path.push(key);
+            if !self.eat(".") {
+                break;
+            }
+        }
+        self.skip_spaces();
+        let comparison = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.eat(token));
+        let comparison = match comparison {
+            Some((_, comparison)) => Some((comparison, self.literal(&[']'])?)),
+            None => None,
+        };
+        Ok(Filter { path, comparison })
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let start = self.position;
+        match self.literal(&[']', '.', ' ', ';', '\n', '=', '!', '<', '>'])? {
+            Value::String(string) => Ok(string),
+            _ => {
+                self.position = start;
+                Err(self.error("expected a string".to_string()))
+            }
+        }
+    }
+
+    // A JSON value, ending at the first of `terminators` outside of strings
+    // and brackets.
+    fn literal(&mut self, terminators: &[char]) -> Result<Value, Error> {
+        self.skip_spaces();
+        let start = self.position;
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        while let Some(c) = self.peek() {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    in_string = false;
+                }
+            } else if depth == 0 && terminators.contains(&c) {
+                break;
+            } else {
+                match c {
+                    '"' => in_string = true,
+                    '[' | '{' => depth += 1,
+                    ']' | '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            self.bump();
+        }
+        let text = self.text[start..self.position].trim();
+        if text.is_empty() {
+            return Err(self.error("expected a JSON value".to_string()));
+        }
+        serde_json::from_str(text).map_err(|e| {
+            let message = format!("invalid value `{}`: {}", text, e);
+            self.position = start;
+            self.error(message)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(expression: &str) -> Vec<Statement> {
+        expression.parse::<Edit>().unwrap().statements
+    }
+
+    fn key(key: &str) -> Step {
+        Step::Key(key.to_string())
+    }
+
+    // The column and message of the error parsing `expression`.
+    fn error(expression: &str) -> (usize, String) {
+        match expression.parse::<Edit>() {
+            Err(Error::Expression { column, message }) => (column, message),
+            other => panic!("{:?} parsed as {:?}", expression, other),
+        }
+    }
+
+    fn edited(expression: &str, document: Value) -> Value {
+        let mut document = document;
+        expression
+            .parse::<Edit>()
+            .unwrap()
+            .apply(&mut document)
+            .unwrap();
+        document
+    }
+
+    #[test]
+    fn parses_paths() {
+        let statements = parse(r#"$.sales[*].extra["a key"][-1].* = null"#);
+        assert_eq!(
+            statements[0].path,
+            [
+                key("sales"),
+                Step::Wildcard,
+                key("extra"),
+                key("a key"),
+                Step::Index(-1),
+                Step::Wildcard
+            ]
+        );
+        assert_eq!(statements[0].operation, Operation::Set(Value::Null));
+    }
+
+    #[test]
+    fn parses_filters() {
+        let statements =
+            parse(r#"sales[?@.unit == "g"].unit = "kg"; products[?discontinued].x = 1"#);
+        assert_eq!(
+            statements[0].path[1],
+            Step::Filter(Filter {
+                path: vec!["unit".to_string()],
+                comparison: Some((Comparison::Equal, json!("g"))),
+            })
+        );
+        assert_eq!(statements[0].operation, Operation::Set(json!("kg")));
+        assert_eq!(
+            statements[1].path[1],
+            Step::Filter(Filter {
+                path: vec!["discontinued".to_string()],
+                comparison: None,
+            })
+        );
+        let statements = parse("sales[?price.net >= 10].x = [1, {\"a\": \"];\"}]");
+        assert_eq!(
+            statements[0].path[1],
+            Step::Filter(Filter {
+                path: vec!["price".to_string(), "net".to_string()],
+                comparison: Some((Comparison::GreaterOrEqual, json!(10))),
+            })
+        );
+        assert_eq!(
+            statements[0].operation,
+            Operation::Set(json!([1, { "a": "];" }]))
+        );
+    }
+
+    #[test]
+    fn parses_operations() {
+        let statements = parse(
+            "sales[*].quantity *= 1000\n\
+             sales[*].quantity -= 0.5\n\
+             delete products[0]\n\
+             rename sales[*].date to sold_at; rename a to \"b c\"",
+        );
+        let operations: Vec<&Operation> = statements
+            .iter()
+            .map(|statement| &statement.operation)
+            .collect();
+        assert_eq!(
+            operations,
+            [
+                &Operation::Arithmetic(Arithmetic::Multiply, 1000.into()),
+                &Operation::Arithmetic(Arithmetic::Subtract, Number::from_f64(0.5).unwrap()),
+                &Operation::Delete,
+                &Operation::Rename("sold_at".to_string()),
+                &Operation::Rename("b c".to_string()),
+            ]
+        );
+        assert_eq!(
+            statements[0].path,
+            [key("sales"), Step::Wildcard, key("quantity")]
+        );
+        // Keywords are keys when not followed by a space.
+        assert_eq!(parse("delete-me = 1")[0].path, [key("delete-me")]);
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        assert_eq!(error("").1, "nothing to do");
+        assert_eq!(error("sales[*].quantity").0, 18);
+        assert_eq!(
+            error("sales[x] = 1"),
+            (7, "expected `*`, `?`, an index or a key".to_string())
+        );
+        assert_eq!(error("a *= \"x\""), (5, "`*=` takes a number".to_string()));
+        assert_eq!(error("rename a[0] to b").1, "only keys can be renamed");
+        assert_eq!(error("rename a tox"), (10, "expected `to`".to_string()));
+        assert_eq!(
+            error("rename a to"),
+            (12, "expected the new key".to_string())
+        );
+        assert_eq!(
+            error("delete a b"),
+            (10, "expected `;`, found `b`".to_string())
+        );
+        assert!(error("a = {").1.starts_with("invalid value `{`"));
+    }
+
+    #[test]
+    fn keeps_the_order_of_keys() {
+        let document = json!({ "z": 1, "m": { "c": 1, "b": 2, "a": 3 }, "a": 2 });
+        let edited = edited("rename m.b to x; delete z; m.d = 4", document);
+        assert_eq!(
+            serde_json::to_string(&edited).unwrap(),
+            r#"{"m":{"c":1,"x":2,"a":3,"d":4},"a":2}"#
+        );
+    }
+
+    #[test]
+    fn applies_to_the_selected_values() {
+        let document = json!({ "sales": [
+            { "unit": "g", "quantity": 1500 },
+            { "unit": "kg", "quantity": 2 },
+            { "unit": "g", "quantity": 250 },
+        ]});
+        let edited = edited(
+            r#"sales[?unit == "g"].quantity /= 1000; sales[?unit == "g"].unit = "kg"; sales[-1].quantity *= 2"#,
+            document,
+        );
+        assert_eq!(
+            edited,
+            json!({ "sales": [
+                { "unit": "kg", "quantity": 1.5 },
+                { "unit": "kg", "quantity": 2 },
+                { "unit": "kg", "quantity": 0.5 },
+            ]})
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use std::fmt;
+
+use crate::merge::Conflict;
+use crate::validate::Violation;
+
+/// Where in an input an error was found. Any part may be unknown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub file: Option<String>,
+    /// 1-based.
+    pub line: Option<u64>,
+    /// 1-based.
+    pub column: Option<u64>,
+}
+
+impl Location {
+    pub fn at(line: u64, column: u64) -> Self {
+        Location {
+            file: None,
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+
+    pub fn line(line: u64) -> Self {
+        Location {
+            file: None,
+            line: Some(line),
+            column: None,
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.file.is_some() || self.line.is_some()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => write!(f, "{}:{}:{}", file, line, column),
+            (Some(file), Some(line), None) => write!(f, "{}:{}", file, line),
+            (Some(file), None, _) => write!(f, "{}", file),
+            (None, Some(line), Some(column)) => write!(f, "line {}, column {}", line, column),
+            (None, Some(line), None) => write!(f, "line {}", line),
+            (None, None, _) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    MissingArgument(String),
+    Usage(String),
+    Config(String),
+    UnknownFormat(String),
+    Io {
+        location: Location,
+        error: std::io::Error,
+    },
+    Json {
+        location: Location,
+        /// Path of the offending value, such as `sales[1].quantity`.
+        field: Option<String>,
+        error: serde_json::Error,
+    },
+    Toml {
+        location: Location,
+        field: Option<String>,
+        error: toml::de::Error,
+    },
+    TomlWrite(toml::ser::Error),
+    Xml {
+        location: Location,
+        error: xml::reader::Error,
+    },
+    XmlWrite(xml::writer::Error),
+    Csv {
+        location: Location,
+        error: csv::Error,
+    },
+    MissingColumn {
+        location: Location,
+        column: String,
+    },
+    InvalidValue {
+        location: Location,
+        field: &'static str,
+        value: String,
+        reason: String,
+    },
+    /// A record without the value a mapping reads a field from.
+    MissingValue {
+        location: Location,
+        field: &'static str,
+        path: String,
+    },
+    /// An edit expression that cannot be parsed.
+    Expression {
+        column: usize,
+        message: String,
+    },
+    /// An edit that cannot be applied to the value at `path`.
+    Edit {
+        path: String,
+        reason: String,
+    },
+    /// Records breaking the validation rules, all of them listed.
+    Violations(Vec<Violation>),
+    /// Records with the same id that differ, when merging with the fail policy.
+    Conflict(Box<Conflict>),
+}
+
+impl Error {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Io { location, .. }
+            | Error::Json { location, .. }
+            | Error::Toml { location, .. }
+            | Error::Xml { location, .. }
+            | Error::Csv { location, .. }
+            | Error::MissingColumn { location, .. }
+            | Error::InvalidValue { location, .. }
+            | Error::MissingValue { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            Error::Io { location, .. }
+            | Error::Json { location, .. }
+            | Error::Toml { location, .. }
+            | Error::Xml { location, .. }
+            | Error::Csv { location, .. }
+            | Error::MissingColumn { location, .. }
+            | Error::InvalidValue { location, .. }
+            | Error::MissingValue { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Records the file the error was found in, unless it is already known.
+    pub fn in_file(mut self, file: &str) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.file.get_or_insert_with(|| file.to_string());
+        }
+        self
+    }
+
+    /// Records the position the error was found at, unless it is already known.
+    pub fn at(mut self, position: Location) -> Self {
+        if let Some(location) = self.location_mut() {
+            if location.line.is_none() {
+                location.line = position.line;
+                location.column = position.column;
+            }
+        }
+        self
+    }
+
+    /// The record field the error is about, when known.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Error::Json { field, .. } | Error::Toml { field, .. } => field.as_deref(),
+            Error::InvalidValue { field, .. } | Error::MissingValue { field, .. } => Some(field),
+            Error::MissingColumn { column, .. } => Some(column),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn message(&self) -> String {
+        Message(self).to_string()
+    }
+
+    /// Whether the error spoils only the record it was found in, so that
+    /// reading can carry on with the next one.
+    pub fn is_record_error(&self) -> bool {
+        match self {
+            Error::InvalidValue { .. } | Error::MissingValue { .. } => true,
+            Error::Json { field, .. } => field.is_some(),
+            Error::Csv { error, .. } => matches!(
+                error.kind(),
+                csv::ErrorKind::UnequalLengths { .. } | csv::ErrorKind::Utf8 { .. }
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the error comes from the command line rather than from the data.
+    pub fn is_usage(&self) -> bool {
+        matches!(
+            self,
+            Error::MissingArgument(_) | Error::Usage(_) | Error::UnknownFormat(_)
+        )
+    }
+}
+
+// serde_json and toml append the position to their messages, which is
+// redundant once it is part of the location.
+fn without_position(message: String) -> String {
+    if let Some(start) = message.rfind(" at line ") {
+        let position = &message[start + " at line ".len()..];
+        if let Some((line, column)) = position.split_once(" column ") {
+            let is_number =
+                |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+            if is_number(line) && is_number(column) {
+                return message[..start].to_string();
+            }
+        }
+    }
+    message
+}
+
+// An error as displayed after its location.
+struct Message<'a>(&'a Error);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = self.location().filter(|location| location.is_known()) {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}", Message(self))
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Error::MissingArgument(what) => write!(f, "missing {}", what),
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Config(message) => write!(f, "configuration error: {}", message),
+            Error::UnknownFormat(name) => write!(f, "unknown format {:?}", name),
+            Error::Io { error, .. } => write!(f, "I/O error: {}", error),
+            Error::Json { field, error, .. } => {
+                write!(f, "JSON error")?;
+                if let Some(field) = field {
+                    write!(f, " in `{}`", field)?;
+                }
+                write!(f, ": {}", without_position(error.to_string()))
+            }
+            Error::Toml { field, error, .. } => {
+                write!(f, "TOML error")?;
+                if let Some(field) = field {
+                    write!(f, " in `{}`", field)?;
+                }
+                write!(f, ": {}", without_position(error.to_string()))
+            }
+            Error::TomlWrite(error) => write!(f, "TOML write error: {}", error),
+            Error::Xml { error, .. } => write!(f, "XML error: {}", error.msg()),
+            Error::XmlWrite(error) => write!(f, "XML write error: {}", error),
+            Error::Csv { error, .. } => write!(f, "CSV error: {}", error),
+            Error::MissingColumn { column, .. } => write!(f, "missing CSV column {:?}", column),
+            Error::MissingValue { field, path, .. } => {
+                write!(f, "nothing at `{}` to read `{}` from", path, field)
+            }
+            Error::InvalidValue {
+                field,
+                value,
+                reason,
+                ..
+            } => write!(f, "invalid value {:?} for `{}`: {}", value, field, reason),
+            Error::Expression { column, message } => {
+                write!(f, "edit expression, column {}: {}", column, message)
+            }
+            Error::Edit { path, reason } => write!(f, "cannot edit `{}`: {}", path, reason),
+            // Each violation names the rule or schema it breaks.
+            Error::Violations(violations) => match violations.len() {
+                1 => write!(f, "1 violation"),
+                count => write!(f, "{} violations", count),
+            },
+            Error::Conflict(conflict) => write!(
+                f,
+                "conflicting {}: {} differs from {} in {}",
+                conflict.record,
+                conflict.other,
+                conflict.first,
+                conflict.fields.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io {
+            location: Location::default(),
+            error,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json {
+            location: match error.line() {
+                0 => Location::default(),
+                line => Location::at(line as u64, error.column() as u64),
+            },
+            field: None,
+            error,
+        }
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for Error {
+    fn from(error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let field = error.path().to_string();
+        match Error::from(error.into_inner()) {
+            Error::Json {
+                location, error, ..
+            } => Error::Json {
+                location,
+                field: Some(field).filter(|field| field != "."),
+                error,
+            },
+            other => other,
+        }
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::Toml {
+            location: match error.line_col() {
+                Some((line, column)) => Location::at(line as u64 + 1, column as u64 + 1),
+                None => Location::default(),
+            },
+            field: None,
+            error,
+        }
+    }
+}
+
+impl From<serde_path_to_error::Error<toml::de::Error>> for Error {
+    fn from(error: serde_path_to_error::Error<toml::de::Error>) -> Self {
+        let field = error.path().to_string();
+        match Error::from(error.into_inner()) {
+            Error::Toml {
+                location, error, ..
+            } => Error::Toml {
+                location,
+                field: Some(field).filter(|field| field != "."),
+                error,
+            },
+            other => other,
+        }
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(error: toml::ser::Error) -> Self {
+        Error::TomlWrite(error)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Self {
+        Error::Csv {
+            location: match error.position() {
+                Some(position) => Location::line(position.line()),
+                None => Location::default(),
+            },
+            error,
+        }
+    }
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(error: xml::reader::Error) -> Self {
+        use xml::common::Position;
+        let position = error.position();
+        Error::Xml {
+            location: Location::at(position.row + 1, position.column + 1),
+            error,
+        }
+    }
+}
+
+impl From<xml::writer::Error> for Error {
+    fn from(error: xml::writer::Error) -> Self {
+        Error::XmlWrite(error)
+    }
+}
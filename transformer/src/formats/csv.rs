@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::{in_file, Records};
+use crate::dates::DateOptions;
+use crate::model::{Field, Fields, Product, Record, Sale, SalesAndProducts};
+use crate::{Error, Location};
+
+pub const PRODUCTS_FILE: &str = "products.csv";
+pub const SALES_FILE: &str = "sales.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteStyle {
+    Always,
+    Necessary,
+    NonNumeric,
+    Never,
+}
+
+impl std::str::FromStr for QuoteStyle {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "always" => Ok(QuoteStyle::Always),
+            "necessary" => Ok(QuoteStyle::Necessary),
+            "non-numeric" => Ok(QuoteStyle::NonNumeric),
+            "never" => Ok(QuoteStyle::Never),
+            _ => Err(Error::Usage(format!("unknown quote style {:?}", name))),
+        }
+    }
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(style: QuoteStyle) -> Self {
+        match style {
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+            QuoteStyle::Never => csv::QuoteStyle::Never,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote: char,
+    pub quote_style: QuoteStyle,
+    /// Column header of a field, keyed by `product.<field>` or `sale.<field>`.
+    /// Fields not listed use their own name.
+    pub headers: HashMap<String, String>,
+    /// Column telling products from sales when both share one file.
+    pub record_type_column: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            quote: '"',
+            quote_style: QuoteStyle::Necessary,
+            headers: HashMap::new(),
+            record_type_column: "record_type".to_string(),
+        }
+    }
+}
+
+impl CsvOptions {
+    fn header<T: Fields>(&self, field: &Field<T>) -> String {
+        self.headers
+            .get(&format!("{}.{}", T::NAME, field.name))
+            .cloned()
+            .unwrap_or_else(|| field.name.to_string())
+    }
+
+    fn byte(name: &str, c: char) -> Result<u8, Error> {
+        if c.is_ascii() {
+            Ok(c as u8)
+        } else {
+            Err(Error::Usage(format!(
+                "CSV {} must be an ASCII character",
+                name
+            )))
+        }
+    }
+
+    fn reader<R: Read>(&self, source: R) -> Result<csv::Reader<R>, Error> {
+        Ok(csv::ReaderBuilder::new()
+            .delimiter(Self::byte("delimiter", self.delimiter)?)
+            .quote(Self::byte("quote", self.quote)?)
+            .from_reader(source))
+    }
+
+    fn writer<W: Write>(&self, sink: W) -> Result<csv::Writer<W>, Error> {
+        Ok(csv::WriterBuilder::new()
+            .delimiter(Self::byte("delimiter", self.delimiter)?)
+            .quote(Self::byte("quote", self.quote)?)
+            .quote_style(self.quote_style.into())
+            .from_writer(sink))
+    }
+}
+
+// Which field, if any, each column of a file fills. Every field needs a column.
+fn columns<T: Fields>(
+    headers: &csv::StringRecord,
+    options: &CsvOptions,
+) -> Result<Vec<Option<&'static Field<T>>>, Error> {
+    for field in T::FIELDS {
+        let header = options.header(field);
+        if !headers.iter().any(|column| column == header) {
+            return Err(Error::MissingColumn {
+                location: Location::line(1),
+                column: header,
+            });
+        }
+    }
+    Ok(headers
+        .iter()
+        .map(|header| {
+            T::FIELDS
+                .iter()
+                .find(|field| options.header(field) == header)
+        })
+        .collect())
+}
+
+fn row_location(row: &csv::StringRecord) -> Location {
+    row.position()
+        .map(|position| Location::line(position.line()))
+        .unwrap_or_default()
+}
+
+fn parse_record<T: Fields>(
+    row: &csv::StringRecord,
+    columns: &[Option<&'static Field<T>>],
+) -> Result<T, Error> {
+    let mut record = T::default();
+    for (value, field) in row.iter().zip(columns) {
+        if let Some(field) = field {
+            (field.set)(&mut record, value).map_err(|reason| Error::InvalidValue {
+                location: row_location(row),
+                field: field.name,
+                value: value.to_string(),
+                reason,
+            })?;
+        }
+    }
+    Ok(record)
+}
+
+/// Reads either a directory holding `products.csv` and `sales.csv`,
+/// or a single file with a record type column.
+pub fn read_csv_path(pathname: &str, options: &CsvOptions) -> Result<SalesAndProducts, Error> {
+    csv_records_path(pathname, options)?.collect()
+}
+
+/// Streams the records of [`read_csv_path`] one row at a time.
+pub fn csv_records_path(pathname: &str, options: &CsvOptions) -> Result<Records, Error> {
+    let mut records: Records = Box::new(std::iter::empty());
+    for (file, reader) in csv_files(pathname, options)? {
+        records = Box::new(records.chain(in_file(reader, &file)));
+    }
+    Ok(records)
+}
+
+/// The files [`read_csv_path`] reads, each with its pathname.
+pub fn csv_files(
+    pathname: &str,
+    options: &CsvOptions,
+) -> Result<Vec<(String, CsvRecords<File>)>, Error> {
+    let path = Path::new(pathname);
+    if path.is_dir() {
+        let products_path = path.join(PRODUCTS_FILE).display().to_string();
+        let sales_path = path.join(SALES_FILE).display().to_string();
+        let products = File::open(&products_path)
+            .map_err(Error::from)
+            .and_then(|file| CsvRecords::products(file, options))
+            .map_err(|e| e.in_file(&products_path))?;
+        let sales = File::open(&sales_path)
+            .map_err(Error::from)
+            .and_then(|file| CsvRecords::sales(file, options))
+            .map_err(|e| e.in_file(&sales_path))?;
+        Ok(vec![(products_path, products), (sales_path, sales)])
+    } else {
+        let records = File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| CsvRecords::mixed(file, options))
+            .map_err(|e| e.in_file(pathname))?;
+        Ok(vec![(pathname.to_string(), records)])
+    }
+}
+
+pub fn read_csv<R: Read>(source: R, options: &CsvOptions) -> Result<SalesAndProducts, Error> {
+    CsvRecords::mixed(source, options)?.collect()
+}
+
+enum Layout {
+    Products(Vec<Option<&'static Field<Product>>>),
+    Sales(Vec<Option<&'static Field<Sale>>>),
+    Mixed {
+        record_type_index: usize,
+        products: Vec<Option<&'static Field<Product>>>,
+        sales: Vec<Option<&'static Field<Sale>>>,
+    },
+}
+
+/// Iterator over the rows of one CSV file.
+pub struct CsvRecords<R: Read> {
+    rows: csv::StringRecordsIntoIter<R>,
+    layout: Layout,
+    // Where the row last read is.
+    location: Location,
+}
+
+impl<R: Read> CsvRecords<R> {
+    /// Reads a file holding only products.
+    pub fn products(source: R, options: &CsvOptions) -> Result<Self, Error> {
+        let mut reader = options.reader(source)?;
+        let layout = Layout::Products(columns(reader.headers()?, options)?);
+        Ok(CsvRecords {
+            rows: reader.into_records(),
+            layout,
+            location: Location::default(),
+        })
+    }
+
+    /// Reads a file holding only sales.
+    pub fn sales(source: R, options: &CsvOptions) -> Result<Self, Error> {
+        let mut reader = options.reader(source)?;
+        let layout = Layout::Sales(columns(reader.headers()?, options)?);
+        Ok(CsvRecords {
+            rows: reader.into_records(),
+            layout,
+            location: Location::default(),
+        })
+    }
+
+    /// Reads a file mixing products and sales, told apart by the record type column.
+    pub fn mixed(source: R, options: &CsvOptions) -> Result<Self, Error> {
+        let mut reader = options.reader(source)?;
+        let headers = reader.headers()?.clone();
+        let record_type_index = headers
+            .iter()
+            .position(|header| header == options.record_type_column)
+            .ok_or_else(|| Error::MissingColumn {
+                location: Location::line(1),
+                column: options.record_type_column.clone(),
+            })?;
+        Ok(CsvRecords {
+            rows: reader.into_records(),
+            layout: Layout::Mixed {
+                record_type_index,
+                products: columns(&headers, options)?,
+                sales: columns(&headers, options)?,
+            },
+            location: Location::default(),
+        })
+    }
+
+    /// Where the row last read is.
+    pub fn location(&self) -> Location {
+        self.location.clone()
+    }
+
+    fn parse(&self, row: &csv::StringRecord) -> Result<Record, Error> {
+        match &self.layout {
+            Layout::Products(columns) => Ok(parse_record(row, columns)?.into()),
+            Layout::Sales(columns) => Ok(parse_record(row, columns)?.into()),
+            Layout::Mixed {
+                record_type_index,
+                products,
+                sales,
+            } => match row.get(*record_type_index).unwrap_or_default() {
+                Product::NAME => Ok(parse_record(row, products)?.into()),
+                Sale::NAME => Ok(parse_record(row, sales)?.into()),
+                record_type => Err(Error::InvalidValue {
+                    location: row_location(row),
+                    field: "record_type",
+                    value: record_type.to_string(),
+                    reason: format!("expected {} or {}", Product::NAME, Sale::NAME),
+                }),
+            },
+        }
+    }
+}
+
+impl<R: Read> Iterator for CsvRecords<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.next()?;
+        Some(row.map_err(Error::from).and_then(|row| {
+            self.location = row_location(&row);
+            self.parse(&row)
+        }))
+    }
+}
+
+/// Writes into a directory (created if needed) when `pathname` is one or ends
+/// with a path separator, otherwise into a single file with a record type column.
+pub fn write_csv_path(
+    pathname: &str,
+    sales_and_products: &SalesAndProducts,
+    options: &CsvOptions,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    let path = Path::new(pathname);
+    if path.is_dir() || pathname.ends_with(std::path::is_separator) {
+        std::fs::create_dir_all(path).map_err(|e| Error::from(e).in_file(pathname))?;
+        write_csv_records(
+            create(&path.join(PRODUCTS_FILE))?,
+            &sales_and_products.products,
+            options,
+            dates,
+        )?;
+        write_csv_records(
+            create(&path.join(SALES_FILE))?,
+            &sales_and_products.sales,
+            options,
+            dates,
+        )
+    } else {
+        write_csv(create(path)?, sales_and_products, options, dates)
+    }
+}
+
+fn create(path: &Path) -> Result<File, Error> {
+    File::create(path).map_err(|e| Error::from(e).in_file(&path.display().to_string()))
+}
+
+pub fn write_csv_records<T: Fields, W: Write>(
+    sink: W,
+    records: &[T],
+    options: &CsvOptions,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    let mut writer = options.writer(sink)?;
+    writer.write_record(T::FIELDS.iter().map(|field| options.header(field)))?;
+    for record in records {
+        writer.write_record(
+            T::FIELDS
+                .iter()
+                .map(|field| dates.field_text(field, record)),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_csv<W: Write>(
+    sink: W,
+    sales_and_products: &SalesAndProducts,
+    options: &CsvOptions,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    let mut writer = options.writer(sink)?;
+    // Columns shared by products and sales, such as `id`, appear only once.
+    let mut headers = vec![options.record_type_column.clone()];
+    for header in Product::FIELDS
+        .iter()
+        .map(|field| options.header(field))
+        .chain(Sale::FIELDS.iter().map(|field| options.header(field)))
+    {
+        if !headers.contains(&header) {
+            headers.push(header);
+        }
+    }
+    writer.write_record(&headers)?;
+    for product in &sales_and_products.products {
+        writer.write_record(row(&headers, product, options, dates))?;
+    }
+    for sale in &sales_and_products.sales {
+        writer.write_record(row(&headers, sale, options, dates))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn row<T: Fields>(
+    headers: &[String],
+    record: &T,
+    options: &CsvOptions,
+    dates: &DateOptions,
+) -> Vec<String> {
+    let mut row = vec![T::NAME.to_string()];
+    for header in &headers[1..] {
+        row.push(
+            T::FIELDS
+                .iter()
+                .find(|field| options.header(field) == *header)
+                .map(|field| dates.field_text(field, record))
+                .unwrap_or_default(),
+        );
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Semicolon-separated, with a column of a name of its own for some fields.
+    fn options() -> CsvOptions {
+        CsvOptions {
+            delimiter: ';',
+            headers: [("product.name", "Product name"), ("sale.quantity", "qty")]
+                .iter()
+                .map(|(field, header)| (field.to_string(), header.to_string()))
+                .collect(),
+            record_type_column: "kind".to_string(),
+            ..CsvOptions::default()
+        }
+    }
+
+    fn sales_and_products() -> SalesAndProducts {
+        SalesAndProducts {
+            products: vec![Product {
+                id: 1,
+                category: "fruit; fresh".to_string(),
+                name: "apple".to_string(),
+            }],
+            sales: vec![Sale {
+                id: "a".to_string(),
+                product_id: 1,
+                date: 1_234_567_890,
+                quantity: 2.5,
+                unit: "kg".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn reads_columns_by_their_mapped_header() {
+        let document = "kind;id;Product name;category;product_id;date;qty;unit;note\n\
+                        product;1;apple;\"fruit; fresh\";;;;;ignored\n\
+                        sale;a;;;1;1234567890;2.5;kg;\n";
+        assert_eq!(
+            read_csv(document.as_bytes(), &options()).unwrap(),
+            sales_and_products()
+        );
+    }
+
+    #[test]
+    fn writes_the_mapped_headers_and_reads_them_back() {
+        let mut written = Vec::new();
+        write_csv(
+            &mut written,
+            &sales_and_products(),
+            &options(),
+            &DateOptions::default(),
+        )
+        .unwrap();
+        let document = String::from_utf8(written).unwrap();
+        assert_eq!(
+            document.lines().next(),
+            Some("kind;id;category;Product name;product_id;date;qty;unit")
+        );
+        assert_eq!(
+            read_csv(document.as_bytes(), &options()).unwrap(),
+            sales_and_products()
+        );
+    }
+
+    #[test]
+    fn names_a_missing_column_by_its_mapped_header() {
+        let document = "kind;id;name;category;product_id;date;qty;unit\n";
+        match read_csv(document.as_bytes(), &options()) {
+            Err(Error::MissingColumn { location, column }) => {
+                assert_eq!(location, Location::line(1));
+                assert_eq!(column, "Product name");
+            }
+            other => panic!("expected a missing column, got {:?}", other),
+        }
+        let document = "id;Product name;category;product_id;date;qty;unit\n";
+        assert!(matches!(
+            read_csv(document.as_bytes(), &options()),
+            Err(Error::MissingColumn { column, .. }) if column == "kind"
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_record_types_and_non_ascii_delimiters() {
+        let document = "kind;id;Product name;category;product_id;date;qty;unit\n\
+                        refund;a;;;1;1;1;kg\n";
+        let error = read_csv(document.as_bytes(), &options()).unwrap_err();
+        assert_eq!(error.field(), Some("record_type"));
+        assert_eq!(error.location(), Some(&Location::line(2)));
+        let options = CsvOptions {
+            delimiter: '§',
+            ..CsvOptions::default()
+        };
+        assert!(read_csv("".as_bytes(), &options).unwrap_err().is_usage());
+    }
+}
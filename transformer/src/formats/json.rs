@@ -0,0 +1,484 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::mapping::JsonMapping;
+use crate::model::{Fields, Product, Record, Sale};
+use crate::schema::{self, JsonSchema};
+use crate::{Error, Location};
+
+/// Reads a `SalesAndProducts`, or any other document such as a `serde_json::Value`.
+pub fn read_json_file<T: DeserializeOwned>(pathname: &str) -> Result<T, Error> {
+    File::open(pathname)
+        .map_err(Error::from)
+        .and_then(read_json)
+        .map_err(|e| e.in_file(pathname))
+}
+
+pub fn read_json<R: Read, T: DeserializeOwned>(mut source: R) -> Result<T, Error> {
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+    let mut deserializer = serde_json::Deserializer::from_str(&text);
+    let document = serde_path_to_error::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(document)
+}
+
+pub fn write_json_file<T: Serialize>(pathname: &str, document: &T) -> Result<(), Error> {
+    let file = File::create(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+    write_json(file, document)
+}
+
+pub fn write_json<W: Write, T: Serialize>(mut sink: W, document: &T) -> Result<(), Error> {
+    sink.write_all(serde_json::to_string_pretty(document)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn json_records_file(
+    pathname: &str,
+    mapping: &JsonMapping,
+) -> Result<JsonRecords<BufReader<File>>, Error> {
+    let file = File::open(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+    JsonRecords::with_mapping(BufReader::new(file), mapping)
+}
+
+#[derive(Clone, Copy)]
+enum Array {
+    Products,
+    Sales,
+}
+
+enum State {
+    Start,
+    InObject { first: bool },
+    InArray { array: Array, index: usize },
+    Done,
+}
+
+/// Iterator over the `products` and `sales` arrays of a document, holding
+/// only one array element in memory at a time.
+pub struct JsonRecords<R: BufRead> {
+    source: R,
+    mapping: JsonMapping,
+    state: State,
+    line: u64,
+    column: u64,
+    value: Vec<u8>,
+    value_start: (u64, u64),
+    check_schema: bool,
+}
+
+impl<R: BufRead> JsonRecords<R> {
+    pub fn new(source: R) -> Self {
+        JsonRecords {
+            source,
+            mapping: JsonMapping::default(),
+            state: State::Start,
+            line: 1,
+            column: 1,
+            value: Vec::new(),
+            value_start: (1, 1),
+            check_schema: false,
+        }
+    }
+
+    /// Checks each record against the schema of its type before reading it,
+    /// failing with all its violations if it does not match. Records laid
+    /// out by a custom mapping are not checked.
+    pub fn checking_schema(mut self) -> Self {
+        self.check_schema = true;
+        self
+    }
+
+    // The violations of the schema of `T` by the record in `self.value`.
+    fn check_value<T: JsonSchema>(&self, path: &str, pointer: &str) -> Result<(), Error> {
+        let value: Value = self.parse_value(path)?;
+        let violations = schema::check_at_pointer(&T::json_schema(), &value, pointer);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Violations(violations))
+        }
+    }
+
+    /// Reads records laid out as `mapping` describes instead of as written.
+    pub fn with_mapping(source: R, mapping: &JsonMapping) -> Result<Self, Error> {
+        mapping.check()?;
+        Ok(JsonRecords {
+            mapping: mapping.clone(),
+            ..Self::new(source)
+        })
+    }
+
+    /// Where the record last read starts.
+    pub fn location(&self) -> Location {
+        Location::at(self.value_start.0, self.value_start.1)
+    }
+
+    fn array_name(&self, array: Array) -> &str {
+        match array {
+            Array::Products => &self.mapping.products,
+            Array::Sales => &self.mapping.sales,
+        }
+    }
+
+    fn syntax_error(&self, message: String) -> Error {
+        Error::from(<serde_json::Error as serde::de::Error>::custom(message))
+            .at(Location::at(self.line, self.column))
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        Ok(self.source.fill_buf()?.first().copied())
+    }
+
+    fn consume(&mut self, byte: u8) {
+        self.source.consume(1);
+        if byte == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if byte & 0xC0 != 0x80 {
+            // Continuation bytes of a UTF-8 character do not start a new column.
+            self.column += 1;
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, Error> {
+        while let Some(byte) = self.peek()? {
+            if !byte.is_ascii_whitespace() {
+                return Ok(Some(byte));
+            }
+            self.consume(byte);
+        }
+        Ok(None)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), Error> {
+        match self.skip_whitespace()? {
+            Some(byte) if byte == expected => {
+                self.consume(byte);
+                Ok(())
+            }
+            Some(byte) => Err(self.syntax_error(format!(
+                "expected `{}`, found `{}`",
+                expected as char, byte as char
+            ))),
+            None => Err(self.syntax_error(format!(
+                "expected `{}`, found end of file",
+                expected as char
+            ))),
+        }
+    }
+
+    // Copies the next complete value, whatever its type, into `self.value`.
+    fn read_value(&mut self) -> Result<(), Error> {
+        self.value.clear();
+        self.skip_whitespace()?;
+        self.value_start = (self.line, self.column);
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        while let Some(byte) = self.peek()? {
+            if !in_string
+                && depth == 0
+                && !self.value.is_empty()
+                && (matches!(byte, b',' | b']' | b'}') || byte.is_ascii_whitespace())
+            {
+                break;
+            }
+            self.consume(byte);
+            self.value.push(byte);
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    in_string = false;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth = depth.saturating_sub(1);
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if self.value.is_empty() {
+            return Err(self.syntax_error("expected a value, found end of file".to_string()));
+        }
+        Ok(())
+    }
+
+    // Parses `self.value`, placing any error at its position in the file.
+    fn parse_value<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let mut deserializer = serde_json::Deserializer::from_slice(&self.value);
+        serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+            let field = match (path, e.path().iter().next()) {
+                ("", _) => None,
+                (path, None) => Some(path.to_string()),
+                (path, Some(_)) => Some(format!("{}.{}", path, e.path())),
+            };
+            let e = e.into_inner();
+            let (start_line, start_column) = self.value_start;
+            let location = match (e.line() as u64, e.column() as u64) {
+                (0, _) => Location::at(start_line, start_column),
+                (1, column) => Location::at(start_line, start_column + column - 1),
+                (line, column) => Location::at(start_line + line - 1, column),
+            };
+            Error::Json {
+                location,
+                field,
+                error: e,
+            }
+        })
+    }
+
+    // Advances to the next array element, leaving it in `self.value`.
+    fn advance(&mut self) -> Result<Option<(Array, usize)>, Error> {
+        loop {
+            match self.state {
+                State::Start => {
+                    self.expect(b'{')?;
+                    self.state = State::InObject { first: true };
+                }
+                State::InObject { first } => {
+                    if self.skip_whitespace()? == Some(b'}') {
+                        self.consume(b'}');
+                        if let Some(byte) = self.skip_whitespace()? {
+                            return Err(self.syntax_error(format!(
+                                "expected end of file, found `{}`",
+                                byte as char
+                            )));
+                        }
+                        self.state = State::Done;
+                        continue;
+                    }
+                    if !first {
+                        self.expect(b',')?;
+                    }
+                    self.read_value()?;
+                    let key: String = self.parse_value("")?;
+                    self.expect(b':')?;
+                    let array = match key.as_str() {
+                        key if key == self.mapping.products => Array::Products,
+                        key if key == self.mapping.sales => Array::Sales,
+                        _ => {
+                            self.read_value()?;
+                            self.state = State::InObject { first: false };
+                            continue;
+                        }
+                    };
+                    self.expect(b'[')?;
+                    self.state = State::InArray { array, index: 0 };
+                }
+                State::InArray { array, index } => {
+                    if self.skip_whitespace()? == Some(b']') {
+                        self.consume(b']');
+                        self.state = State::InObject { first: false };
+                        continue;
+                    }
+                    if index > 0 {
+                        self.expect(b',')?;
+                    }
+                    self.read_value()?;
+                    self.state = State::InArray {
+                        array,
+                        index: index + 1,
+                    };
+                    return Ok(Some((array, index)));
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+
+    // Parses `self.value` as a record whose fields are where the mapping says.
+    fn parse_mapped<T: Fields>(
+        &self,
+        path: &str,
+        field_path: impl Fn(&str) -> String,
+    ) -> Result<T, Error> {
+        let value: Value = self.parse_value(path)?;
+        let (line, column) = self.value_start;
+        let mut record = T::default();
+        for field in T::FIELDS {
+            let field_path = field_path(field.name);
+            let location = Location::at(line, column);
+            let found = match lookup(&value, &field_path) {
+                Some(Value::Null) | None => {
+                    return Err(Error::MissingValue {
+                        location,
+                        field: field.name,
+                        path: field_path,
+                    })
+                }
+                Some(found) => found,
+            };
+            let text = match found {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            if let Err(reason) = (field.set)(&mut record, &text) {
+                return Err(Error::InvalidValue {
+                    location,
+                    field: field.name,
+                    value: text,
+                    reason,
+                });
+            }
+        }
+        Ok(record)
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('/')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| value.get(key))
+}
+
+impl<R: BufRead> Iterator for JsonRecords<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(Some((array, index))) => {
+                let path = format!("{}[{}]", self.array_name(array), index);
+                let mapping = &self.mapping;
+                if self.check_schema && mapping.has_default_fields() {
+                    let pointer = format!("/{}/{}", self.array_name(array), index);
+                    let checked = match array {
+                        Array::Products => self.check_value::<Product>(&path, &pointer),
+                        Array::Sales => self.check_value::<Sale>(&path, &pointer),
+                    };
+                    if let Err(e) = checked {
+                        return Some(Err(e));
+                    }
+                }
+                Some(match (array, mapping.has_default_fields()) {
+                    (Array::Products, true) => self.parse_value(&path).map(Record::Product),
+                    (Array::Sales, true) => self.parse_value(&path).map(Record::Sale),
+                    (Array::Products, false) => self
+                        .parse_mapped::<Product>(&path, |field| mapping.product_field_path(field))
+                        .map(Record::Product),
+                    (Array::Sales, false) => self
+                        .parse_mapped::<Sale>(&path, |field| mapping.sale_field_path(field))
+                        .map(Record::Sale),
+                })
+            }
+            Ok(None) => None,
+            Err(e) => {
+                // The document structure is lost, so nothing more can be read.
+                self.state = State::Done;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SalesAndProducts;
+
+    use super::*;
+
+    fn read(document: &str) -> Result<SalesAndProducts, Error> {
+        JsonRecords::new(document.as_bytes()).collect()
+    }
+
+    #[test]
+    fn reads_the_fixtures_as_serde_json_does() {
+        for pathname in ["data/sales.json", "data/sales_out.json"] {
+            let text = std::fs::read_to_string(pathname).unwrap();
+            let expected: SalesAndProducts = serde_json::from_str(&text).unwrap();
+            assert_eq!(read(&text).unwrap(), expected, "{}", pathname);
+        }
+    }
+
+    #[test]
+    fn skips_nested_values_of_unknown_keys() {
+        let document = r#"{
+            "meta": {"tags": ["a", {"b": [1, 2, {"c": "]}"}]}], "n": null},
+            "products": [{"id": 1, "category": "fruit", "name": "apple"}],
+            "more": [[], {}, [[{"x": "}"}]]],
+            "sales": []
+        }"#;
+        let expected: SalesAndProducts = serde_json::from_str(document).unwrap();
+        assert_eq!(read(document).unwrap(), expected);
+        assert_eq!(expected.products.len(), 1);
+    }
+
+    #[test]
+    fn reads_escaped_strings() {
+        let document = r#"{"products": [
+            {"id": 1, "category": "say \"hi\", \\o/", "name": "caf\u00e9 \ud83c\udf4e ]},"}
+        ], "sales": []}"#;
+        let read = read(document).unwrap();
+        assert_eq!(read.products[0].category, r#"say "hi", \o/"#);
+        assert_eq!(read.products[0].name, "caf\u{e9} \u{1f34e} ]},");
+        assert_eq!(read, serde_json::from_str(document).unwrap());
+    }
+
+    #[test]
+    fn reads_numbers_with_exponents() {
+        let document = r#"{"sales": [
+            {"id": "s1", "product_id": 1, "date": 1234567890, "quantity": 2.5e3, "unit": "g"},
+            {"id": "s2", "product_id": 1, "date": 1234567890, "quantity": 25E-1, "unit": "kg"}
+        ]}"#;
+        let read = read(document).unwrap();
+        assert_eq!(read.sales[0].quantity, 2500.0);
+        assert_eq!(read.sales[1].quantity, 2.5);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let document = r#"{"products": [{"id": 1, "category": "fruit", "name": "apple"}, {"id": 2"#;
+        let records: Vec<_> = JsonRecords::new(document.as_bytes()).collect();
+        assert!(records[0].is_ok());
+        assert!(records[1..].iter().all(Result::is_err));
+        assert!(!records
+            .last()
+            .unwrap()
+            .as_ref()
+            .unwrap_err()
+            .is_record_error());
+        for truncated in [
+            r#"{"products": ["#,
+            r#"{"products": []"#,
+            r#"{"products""#,
+            "",
+        ] {
+            assert!(read(truncated).is_err(), "{:?}", truncated);
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_garbage() {
+        assert!(read(r#"{"products": []} x"#).is_err());
+        assert!(read(r#"{"products": []}}"#).is_err());
+        assert!(read("{\"products\": []}\n\n").is_ok());
+    }
+
+    #[test]
+    fn names_the_file_it_cannot_create() {
+        let pathname = std::env::temp_dir()
+            .join("transformer_no_such_directory")
+            .join("out.json")
+            .display()
+            .to_string();
+        let error = write_json_file(&pathname, &SalesAndProducts::default()).unwrap_err();
+        assert!(error.to_string().starts_with(&format!("{}: ", pathname)));
+    }
+}
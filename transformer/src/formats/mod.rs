@@ -0,0 +1,266 @@
+pub mod csv;
+pub mod json;
+pub mod toml;
+pub mod xml;
+
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::dates::{self, DateFormat, DateOptions};
+use crate::mapping::Mapping;
+use crate::model::{Record, SalesAndProducts};
+use crate::schema;
+use crate::units::{self, Registry, UnitMode, UnitsConfig};
+use crate::{Error, Location};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+    Xml,
+    Csv,
+}
+
+/// Settings for the formats that have any.
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    pub xml: xml::XmlWriteOptions,
+    pub csv: csv::CsvOptions,
+    /// Where records are found when reading XML and JSON.
+    pub mapping: Mapping,
+    /// How the units of sales are checked or converted as they are read.
+    pub units: UnitsConfig,
+    /// How dates are written, and the range of dates read.
+    pub dates: DateOptions,
+    /// Whether JSON and TOML input is checked against the schema of
+    /// `SalesAndProducts` before it is read, to report all mistakes at once.
+    pub check_schema: bool,
+}
+
+impl Format {
+    /// Guesses the format of a file from its extension.
+    /// A directory is taken to hold CSV files.
+    pub fn from_path(pathname: &str) -> Option<Format> {
+        let path = Path::new(pathname);
+        if path.is_dir() || pathname.ends_with(std::path::is_separator) {
+            return Some(Format::Csv);
+        }
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.parse().ok())
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "toml" => Ok(Format::Toml),
+            "json" => Ok(Format::Json),
+            "xml" => Ok(Format::Xml),
+            "csv" => Ok(Format::Csv),
+            _ => Err(Error::UnknownFormat(name.to_string())),
+        }
+    }
+}
+
+pub fn read_file(
+    format: Format,
+    pathname: &str,
+    options: &FormatOptions,
+) -> Result<SalesAndProducts, Error> {
+    if options.check_schema {
+        return read_checked(open_checked(format, pathname, options)?);
+    }
+    if options.units.mode != UnitMode::Keep || options.dates.has_range() {
+        return open_records(format, pathname, options)?.collect();
+    }
+    match format {
+        Format::Toml => toml::read_toml_file(pathname),
+        Format::Json if options.mapping.json.is_default() => json::read_json_file(pathname),
+        Format::Json | Format::Xml => open_records(format, pathname, options)?.collect(),
+        Format::Csv => csv::read_csv_path(pathname, &options.csv),
+    }
+}
+
+/// Reads a file as a dynamic document. JSON and TOML files are read as they
+/// are, other formats as their `SalesAndProducts`.
+pub fn read_value(format: Format, pathname: &str, options: &FormatOptions) -> Result<Value, Error> {
+    match format {
+        Format::Toml => toml::read_toml_file(pathname),
+        Format::Json => json::read_json_file(pathname),
+        Format::Xml | Format::Csv => {
+            Ok(serde_json::to_value(read_file(format, pathname, options)?)?)
+        }
+    }
+}
+
+pub fn write_value(
+    format: Format,
+    pathname: &str,
+    document: &Value,
+    options: &FormatOptions,
+) -> Result<(), Error> {
+    match format {
+        Format::Toml => toml::write_toml_file(pathname, document),
+        Format::Json => json::write_json_file(pathname, document),
+        Format::Xml | Format::Csv => {
+            let sales_and_products: SalesAndProducts = serde_path_to_error::deserialize(document)?;
+            write_file(format, pathname, &sales_and_products, options)
+        }
+    }
+}
+
+pub type Records = Box<dyn Iterator<Item = Result<Record, Error>>>;
+
+// Names `pathname` in the errors of `records` that do not name a file yet.
+pub(crate) fn in_file(
+    records: impl Iterator<Item = Result<Record, Error>> + 'static,
+    pathname: &str,
+) -> impl Iterator<Item = Result<Record, Error>> + 'static {
+    let pathname = pathname.to_string();
+    records.map(move |record| record.map_err(|e| e.in_file(&pathname)))
+}
+
+/// Reads a file one record at a time. TOML files are still parsed whole.
+pub fn read_records(
+    format: Format,
+    pathname: &str,
+    options: &FormatOptions,
+) -> Result<Records, Error> {
+    if options.check_schema {
+        return open_checked(format, pathname, options);
+    }
+    open_records(format, pathname, options)
+}
+
+// Records are checked one by one as JSON is streamed, failing with the
+// violations of each that does not match. TOML is parsed whole anyway, so it
+// is checked whole first. JSON laid out by a custom mapping has a schema of
+// its own, so is not checked.
+fn open_checked(format: Format, pathname: &str, options: &FormatOptions) -> Result<Records, Error> {
+    if format == Format::Toml {
+        schema::check_file::<SalesAndProducts>(format, pathname)?;
+    }
+    open_records(format, pathname, options)
+}
+
+// Reads checked records, failing with the violations of all of them.
+fn read_checked(records: Records) -> Result<SalesAndProducts, Error> {
+    let mut sales_and_products = SalesAndProducts::default();
+    let mut violations = Vec::new();
+    for record in records {
+        match record {
+            Ok(record) => sales_and_products.extend(Some(record)),
+            Err(Error::Violations(found)) => violations.extend(found),
+            Err(e) => return Err(e),
+        }
+    }
+    if violations.is_empty() {
+        Ok(sales_and_products)
+    } else {
+        Err(Error::Violations(violations))
+    }
+}
+
+fn open_records(format: Format, pathname: &str, options: &FormatOptions) -> Result<Records, Error> {
+    let units = &options.units;
+    let records: Records = match format {
+        Format::Toml => {
+            let records = toml::read_toml_file::<SalesAndProducts>(pathname)?
+                .into_iter()
+                .map(Ok);
+            // TOML is parsed whole, so where each record is is not known.
+            with_units(records, |_| Location::default(), units)?
+        }
+        Format::Json => {
+            let records = json::json_records_file(pathname, &options.mapping.json)?;
+            if options.check_schema {
+                with_units(
+                    records.checking_schema(),
+                    json::JsonRecords::location,
+                    units,
+                )?
+            } else {
+                with_units(records, json::JsonRecords::location, units)?
+            }
+        }
+        Format::Xml => {
+            let records = xml::xml_records_file(pathname, &options.mapping.xml)?;
+            with_units(records, xml::XmlRecords::location, units)?
+        }
+        Format::Csv => {
+            let mut records: Records = Box::new(std::iter::empty());
+            for (file, reader) in csv::csv_files(pathname, &options.csv)? {
+                let reader = with_units(reader, csv::CsvRecords::location, units)?;
+                records = Box::new(records.chain(in_file(reader, &file)));
+            }
+            records
+        }
+    };
+    let records: Records = if options.dates.has_range() {
+        Box::new(dates::filter_dates(records, options.dates.clone()))
+    } else {
+        records
+    };
+    Ok(Box::new(in_file(records, pathname)))
+}
+
+// Applies the unit mode of `units` to what one reader reads, placing the
+// errors where `location` says its last record is.
+fn with_units<R>(
+    records: R,
+    location: fn(&R) -> Location,
+    units: &UnitsConfig,
+) -> Result<Records, Error>
+where
+    R: Iterator<Item = Result<Record, Error>> + 'static,
+{
+    Ok(match units.mode {
+        UnitMode::Keep => Box::new(records),
+        mode => Box::new(units::apply_units(
+            records,
+            location,
+            Registry::new(units)?,
+            mode,
+        )),
+    })
+}
+
+pub fn write_file(
+    format: Format,
+    pathname: &str,
+    sales_and_products: &SalesAndProducts,
+    options: &FormatOptions,
+) -> Result<(), Error> {
+    match format {
+        Format::Toml | Format::Json if options.dates.format == DateFormat::Iso => {
+            let mut document = serde_json::to_value(sales_and_products)?;
+            write_dates(&mut document, &options.dates);
+            write_value(format, pathname, &document, options)
+        }
+        Format::Toml => toml::write_toml_file(pathname, sales_and_products),
+        Format::Json => json::write_json_file(pathname, sales_and_products),
+        Format::Xml => {
+            xml::write_xml_file(pathname, sales_and_products, &options.xml, &options.dates)
+        }
+        Format::Csv => {
+            csv::write_csv_path(pathname, sales_and_products, &options.csv, &options.dates)
+        }
+    }
+}
+
+// Replaces the epochs of the sales of a document with dates written as chosen.
+fn write_dates(document: &mut Value, dates: &DateOptions) {
+    let sales = document.get_mut("sales").and_then(Value::as_array_mut);
+    for sale in sales.into_iter().flatten() {
+        if let Some(date) = sale.get_mut("date") {
+            if let Some(epoch) = date.as_i64() {
+                *date = Value::String(dates.format(epoch));
+            }
+        }
+    }
+}
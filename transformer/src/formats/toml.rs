@@ -0,0 +1,34 @@
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Error;
+
+/// Reads a `SalesAndProducts`, or any other document such as a `serde_json::Value`.
+pub fn read_toml_file<T: DeserializeOwned>(pathname: &str) -> Result<T, Error> {
+    std::fs::File::open(pathname)
+        .map_err(Error::from)
+        .and_then(read_toml)
+        .map_err(|e| e.in_file(pathname))
+}
+
+pub fn read_toml<R: Read, T: DeserializeOwned>(mut source: R) -> Result<T, Error> {
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+    let mut deserializer = toml::Deserializer::new(&text);
+    Ok(serde_path_to_error::deserialize(&mut deserializer)?)
+}
+
+pub fn write_toml_file<T: Serialize>(pathname: &str, document: &T) -> Result<(), Error> {
+    let file = std::fs::File::create(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+    write_toml(file, document)
+}
+
+pub fn write_toml<W: Write, T: Serialize>(mut sink: W, document: &T) -> Result<(), Error> {
+    // Going through `toml::Value` puts plain values ahead of tables, which
+    // TOML requires but maps sorted by key, like a `serde_json::Value`, do not.
+    let document = toml::Value::try_from(document)?;
+    sink.write_all(toml::to_string_pretty(&document)?.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,617 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent as WriterEvent};
+
+use crate::dates::DateOptions;
+use crate::mapping::XmlMapping;
+use crate::model::{Field, Fields, Product, Record, Sale, SalesAndProducts};
+use crate::{Error, Location};
+
+pub const ROOT_ELEMENT: &str = "sales-and-products";
+pub const PRODUCT_ELEMENT: &str = "product";
+pub const SALE_ELEMENT: &str = "sale";
+
+/// Child elements of `<product>`, bound to the `Product` field they fill,
+/// unless the mapping reads the field from elsewhere.
+pub const PRODUCT_BINDINGS: &[(&str, &str)] =
+    &[("id", "id"), ("category", "category"), ("name", "name")];
+
+/// Child elements of `<sale>`, bound to the `Sale` field they fill.
+pub const SALE_BINDINGS: &[(&str, &str)] = &[
+    ("id", "id"),
+    ("product-id", "product_id"),
+    ("date", "date"),
+    ("quantity", "quantity"),
+    ("unit", "unit"),
+];
+
+// Where a field is read from, relative to its record element.
+struct Binding<T: 'static> {
+    elements: Vec<String>,
+    attribute: Option<String>,
+    field: &'static Field<T>,
+}
+
+// The elements holding one kind of record and where each of its fields is.
+struct Layout<T: 'static> {
+    elements: Vec<String>,
+    bindings: Vec<Binding<T>>,
+}
+
+impl<T: Fields> Layout<T> {
+    fn new(record_path: &str, field_path: impl Fn(&str) -> String) -> Self {
+        let bindings = T::FIELDS
+            .iter()
+            .map(|field| {
+                let mut elements = split_path(&field_path(field.name));
+                let attribute = match elements.last() {
+                    Some(last) if last.starts_with('@') => {
+                        elements.pop().map(|last| last[1..].to_string())
+                    }
+                    _ => None,
+                };
+                Binding {
+                    elements,
+                    attribute,
+                    field,
+                }
+            })
+            .collect();
+        Layout {
+            elements: split_path(record_path),
+            bindings,
+        }
+    }
+
+    fn matches(&self, stack: &[String]) -> bool {
+        !self.elements.is_empty() && stack.ends_with(&self.elements)
+    }
+}
+
+fn split_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|element| !element.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// A record being filled from its element, its attributes and its descendants.
+struct PartialRecord<T: Fields + 'static> {
+    record: T,
+    // Length of the element stack once the record element was entered.
+    depth: usize,
+    field: Option<(&'static Field<T>, usize)>,
+    field_location: Location,
+    text: String,
+    // Set once a field failed to parse, so the record is never yielded.
+    rejected: bool,
+    // Names of the fields given a value so far.
+    set_fields: Vec<&'static str>,
+    // Where the record element starts.
+    location: Location,
+}
+
+impl<T: Fields> PartialRecord<T> {
+    fn new(depth: usize, location: Location) -> Self {
+        PartialRecord {
+            record: T::default(),
+            depth,
+            field: None,
+            field_location: Location::default(),
+            text: String::new(),
+            rejected: false,
+            set_fields: Vec::new(),
+            location,
+        }
+    }
+
+    // Called for the record element itself too, with an empty `elements`.
+    fn start_element(
+        &mut self,
+        layout: &Layout<T>,
+        elements: &[String],
+        attributes: &[OwnedAttribute],
+        location: Location,
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+        for binding in layout.bindings.iter().filter(|b| b.elements == elements) {
+            match &binding.attribute {
+                Some(attribute) => {
+                    let value = attributes
+                        .iter()
+                        .find(|candidate| candidate.name.local_name == *attribute);
+                    if let Some(value) = value {
+                        let set = self.set(binding.field, &value.value, &location);
+                        result = result.and(set);
+                    }
+                }
+                None => {
+                    self.field = Some((binding.field, self.depth + elements.len()));
+                    self.field_location = location.clone();
+                    self.text.clear();
+                }
+            }
+        }
+        result
+    }
+
+    fn characters(&mut self, characters: &str) {
+        if self.field.is_some() {
+            self.text.push_str(characters);
+        }
+    }
+
+    fn end_element(&mut self, depth: usize) -> Result<(), Error> {
+        match self.field {
+            Some((field, field_depth)) if field_depth == depth => {
+                self.field = None;
+                let text = std::mem::take(&mut self.text);
+                let location = self.field_location.clone();
+                self.set(field, &text, &location)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Only the first rejected field of a record is reported.
+    fn set(
+        &mut self,
+        field: &'static Field<T>,
+        value: &str,
+        location: &Location,
+    ) -> Result<(), Error> {
+        if self.rejected {
+            return Ok(());
+        }
+        self.set_fields.push(field.name);
+        (field.set)(&mut self.record, value).map_err(|reason| {
+            self.rejected = true;
+            Error::InvalidValue {
+                location: location.clone(),
+                field: field.name,
+                value: value.to_string(),
+                reason,
+            }
+        })
+    }
+
+    // The record once its own end tag is reached, unless it was rejected,
+    // or an error naming the first field nothing was found for.
+    fn finish(self, layout: &Layout<T>) -> Result<Option<T>, Error> {
+        if self.rejected {
+            return Ok(None);
+        }
+        let missing = layout
+            .bindings
+            .iter()
+            .find(|binding| !self.set_fields.contains(&binding.field.name));
+        match missing {
+            Some(binding) => {
+                let mut path = binding.elements.join("/");
+                if let Some(attribute) = &binding.attribute {
+                    if !path.is_empty() {
+                        path.push('/');
+                    }
+                    path.push('@');
+                    path.push_str(attribute);
+                }
+                Err(Error::MissingValue {
+                    location: self.location,
+                    field: binding.field.name,
+                    path,
+                })
+            }
+            None => Ok(Some(self.record)),
+        }
+    }
+}
+
+enum Scope {
+    Other,
+    InProduct(PartialRecord<Product>),
+    InSale(PartialRecord<Sale>),
+}
+
+pub fn read_xml_file(pathname: &str) -> Result<SalesAndProducts, Error> {
+    xml_records_file(pathname, &XmlMapping::default())
+        .and_then(|records| records.collect())
+        .map_err(|e| e.in_file(pathname))
+}
+
+pub fn read_xml<R: Read>(source: R) -> Result<SalesAndProducts, Error> {
+    XmlRecords::new(source).collect()
+}
+
+pub fn xml_records_file(
+    pathname: &str,
+    mapping: &XmlMapping,
+) -> Result<XmlRecords<BufReader<File>>, Error> {
+    let file = File::open(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+    XmlRecords::with_mapping(BufReader::new(file), mapping)
+}
+
+/// Iterator over the products and sales of a document, in document order.
+pub struct XmlRecords<R: Read> {
+    reader: EventReader<R>,
+    finished: bool,
+    products: Layout<Product>,
+    sales: Layout<Sale>,
+    // Local names of the open elements.
+    stack: Vec<String>,
+    scope: Scope,
+    // Where the record last read starts.
+    location: Location,
+}
+
+impl<R: Read> XmlRecords<R> {
+    pub fn new(source: R) -> Self {
+        Self::with_layouts(source, &XmlMapping::default())
+    }
+
+    /// Reads records laid out as `mapping` describes instead of as written.
+    pub fn with_mapping(source: R, mapping: &XmlMapping) -> Result<Self, Error> {
+        mapping.check()?;
+        Ok(Self::with_layouts(source, mapping))
+    }
+
+    fn with_layouts(source: R, mapping: &XmlMapping) -> Self {
+        XmlRecords {
+            reader: EventReader::new(source),
+            finished: false,
+            products: Layout::new(&mapping.product, |field| mapping.product_field_path(field)),
+            sales: Layout::new(&mapping.sale, |field| mapping.sale_field_path(field)),
+            stack: Vec::new(),
+            scope: Scope::Other,
+            location: Location::default(),
+        }
+    }
+
+    /// Where the record last read starts.
+    pub fn location(&self) -> Location {
+        self.location.clone()
+    }
+
+    fn handle(&mut self, event: XmlEvent) -> Result<Option<Record>, Error> {
+        let position = self.reader.position();
+        let location = Location::at(position.row + 1, position.column + 1);
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                self.stack.push(name.local_name);
+                let depth = self.stack.len();
+                match &mut self.scope {
+                    Scope::Other => {
+                        if self.products.matches(&self.stack) {
+                            let mut partial = PartialRecord::new(depth, location.clone());
+                            let result =
+                                partial.start_element(&self.products, &[], &attributes, location);
+                            self.scope = Scope::InProduct(partial);
+                            result?;
+                        } else if self.sales.matches(&self.stack) {
+                            let mut partial = PartialRecord::new(depth, location.clone());
+                            let result =
+                                partial.start_element(&self.sales, &[], &attributes, location);
+                            self.scope = Scope::InSale(partial);
+                            result?;
+                        }
+                    }
+                    Scope::InProduct(partial) => {
+                        let elements = &self.stack[partial.depth..];
+                        partial.start_element(&self.products, elements, &attributes, location)?;
+                    }
+                    Scope::InSale(partial) => {
+                        let elements = &self.stack[partial.depth..];
+                        partial.start_element(&self.sales, elements, &attributes, location)?;
+                    }
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => match &mut self.scope {
+                Scope::InProduct(partial) => partial.characters(&text),
+                Scope::InSale(partial) => partial.characters(&text),
+                Scope::Other => {}
+            },
+            XmlEvent::EndElement { .. } => {
+                let depth = self.stack.len();
+                self.stack.pop();
+                let result = match &mut self.scope {
+                    Scope::InProduct(partial) => partial.end_element(depth),
+                    Scope::InSale(partial) => partial.end_element(depth),
+                    Scope::Other => Ok(()),
+                };
+                let closed = match &self.scope {
+                    Scope::InProduct(partial) if partial.depth == depth => Some(&partial.location),
+                    Scope::InSale(partial) if partial.depth == depth => Some(&partial.location),
+                    _ => None,
+                };
+                if let Some(location) = closed {
+                    self.location = location.clone();
+                    let record = match std::mem::replace(&mut self.scope, Scope::Other) {
+                        Scope::InProduct(partial) => partial
+                            .finish(&self.products)
+                            .map(|record| record.map(Record::Product)),
+                        Scope::InSale(partial) => partial
+                            .finish(&self.sales)
+                            .map(|record| record.map(Record::Sale)),
+                        Scope::Other => Ok(None),
+                    };
+                    result?;
+                    return record;
+                }
+                result?;
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for XmlRecords<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            match self.reader.next() {
+                Ok(XmlEvent::EndDocument) => self.finished = true,
+                Ok(event) => match self.handle(event) {
+                    Ok(Some(record)) => return Some(Ok(record)),
+                    Ok(None) => {}
+                    Err(e) => return Some(Err(e)),
+                },
+                Err(e) => {
+                    // The parser cannot recover from malformed XML.
+                    self.finished = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XmlEncoding {
+    Utf8,
+    /// Pure ASCII output, with any other character written as a character reference.
+    UsAscii,
+}
+
+impl std::str::FromStr for XmlEncoding {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "utf-8" => Ok(XmlEncoding::Utf8),
+            "us-ascii" => Ok(XmlEncoding::UsAscii),
+            _ => Err(Error::Usage(format!(
+                "unknown XML encoding {:?}, expected utf-8 or us-ascii",
+                name
+            ))),
+        }
+    }
+}
+
+impl XmlEncoding {
+    fn label(self) -> &'static str {
+        match self {
+            XmlEncoding::Utf8 => "utf-8",
+            XmlEncoding::UsAscii => "us-ascii",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct XmlWriteOptions {
+    /// String repeated once per nesting level, or `None` for a single-line document.
+    pub indent: Option<String>,
+    pub encoding: XmlEncoding,
+}
+
+impl Default for XmlWriteOptions {
+    fn default() -> Self {
+        XmlWriteOptions {
+            indent: Some("    ".to_string()),
+            encoding: XmlEncoding::Utf8,
+        }
+    }
+}
+
+pub fn write_xml_file(
+    pathname: &str,
+    sales_and_products: &SalesAndProducts,
+    options: &XmlWriteOptions,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    let file = File::create(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+    write_xml(
+        std::io::BufWriter::new(file),
+        sales_and_products,
+        options,
+        dates,
+    )
+}
+
+pub fn write_xml<W: Write>(
+    sink: W,
+    sales_and_products: &SalesAndProducts,
+    options: &XmlWriteOptions,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    match options.encoding {
+        XmlEncoding::Utf8 => write_document(sink, sales_and_products, options, dates),
+        XmlEncoding::UsAscii => {
+            write_document(AsciiEscaper::new(sink), sales_and_products, options, dates)
+        }
+    }
+}
+
+fn write_document<W: Write>(
+    sink: W,
+    sales_and_products: &SalesAndProducts,
+    options: &XmlWriteOptions,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(options.indent.is_some())
+        .indent_string(options.indent.clone().unwrap_or_default())
+        .create_writer(sink);
+    writer.write(WriterEvent::StartDocument {
+        version: xml::common::XmlVersion::Version10,
+        encoding: Some(options.encoding.label()),
+        standalone: None,
+    })?;
+    writer.write(WriterEvent::start_element(ROOT_ELEMENT))?;
+    for product in &sales_and_products.products {
+        write_record(
+            &mut writer,
+            PRODUCT_ELEMENT,
+            PRODUCT_BINDINGS,
+            product,
+            dates,
+        )?;
+    }
+    for sale in &sales_and_products.sales {
+        write_record(&mut writer, SALE_ELEMENT, SALE_BINDINGS, sale, dates)?;
+    }
+    writer.write(WriterEvent::end_element())?;
+    let mut sink = writer.into_inner();
+    if options.indent.is_some() {
+        sink.write_all(b"\n")?;
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+// Passes on what xml-rs writes with every character outside ASCII as a
+// character reference. Markup is ASCII already, so only text content is
+// ever escaped here.
+struct AsciiEscaper<W: Write> {
+    sink: W,
+    // The start of a character cut off at the end of the last write.
+    partial: Vec<u8>,
+}
+
+impl<W: Write> AsciiEscaper<W> {
+    fn new(sink: W) -> Self {
+        AsciiEscaper {
+            sink,
+            partial: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for AsciiEscaper<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            if self.partial.is_empty() {
+                let ascii = rest.iter().take_while(|byte| byte.is_ascii()).count();
+                self.sink.write_all(&rest[..ascii])?;
+                rest = &rest[ascii..];
+                if rest.is_empty() {
+                    break;
+                }
+            }
+            self.partial.push(rest[0]);
+            rest = &rest[1..];
+            match std::str::from_utf8(&self.partial) {
+                Ok(text) => {
+                    for c in text.chars() {
+                        write!(self.sink, "&#x{:X};", c as u32)?;
+                    }
+                    self.partial.clear();
+                }
+                // More bytes of the character are to come.
+                Err(e) if e.error_len().is_none() => {}
+                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            }
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+fn write_record<W: Write, T: Fields>(
+    writer: &mut EventWriter<W>,
+    element: &str,
+    bindings: &[(&str, &str)],
+    record: &T,
+    dates: &DateOptions,
+) -> Result<(), Error> {
+    writer.write(WriterEvent::start_element(element))?;
+    for (field_element, field_name) in bindings {
+        if let Some(field) = T::field(field_name) {
+            writer.write(WriterEvent::start_element(*field_element))?;
+            writer.write(WriterEvent::characters(&dates.field_text(field, record)))?;
+            writer.write(WriterEvent::end_element())?;
+        }
+    }
+    writer.write(WriterEvent::end_element())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_field_is_a_record_error() {
+        let document = r#"<sales-and-products>
+            <product><id>1</id><category>fruit</category><name>apple</name></product>
+            <sale><id>s1</id><date>1</date><quantity>2</quantity><unit>kg</unit></sale>
+        </sales-and-products>"#;
+        let records: Vec<_> = XmlRecords::new(document.as_bytes()).collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].is_ok());
+        let error = records[1].as_ref().unwrap_err();
+        assert!(error.is_record_error());
+        assert_eq!(error.field(), Some("product_id"));
+        assert!(read_xml(document.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn writes_on_one_line_in_ascii() {
+        let sales_and_products = SalesAndProducts {
+            products: vec![Product {
+                id: 1,
+                category: "caf\u{e9}".to_string(),
+                name: "apple".to_string(),
+            }],
+            sales: Vec::new(),
+        };
+        let options = XmlWriteOptions {
+            indent: None,
+            encoding: "us-ascii".parse().unwrap(),
+        };
+        let mut document = Vec::new();
+        write_xml(
+            &mut document,
+            &sales_and_products,
+            &options,
+            &DateOptions::default(),
+        )
+        .unwrap();
+        let document = String::from_utf8(document).unwrap();
+        assert!(!document.contains('\n'));
+        assert!(document.contains("<category>caf&#xE9;</category>"));
+        assert_eq!(read_xml(document.as_bytes()).unwrap(), sales_and_products);
+        assert!("latin-1".parse::<XmlEncoding>().is_err());
+    }
+
+    #[test]
+    fn escapes_characters_cut_across_writes() {
+        let mut escaped = AsciiEscaper::new(Vec::new());
+        for byte in "a\u{e9}\u{1F34E}b".as_bytes() {
+            escaped.write_all(&[*byte]).unwrap();
+        }
+        assert_eq!(escaped.sink, b"a&#xE9;&#x1F34E;b");
+        assert!(AsciiEscaper::new(Vec::new()).write_all(&[0xff]).is_err());
+    }
+}
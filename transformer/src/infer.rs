@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{json, Map, Value};
+
+/// What was seen of the values found at one place of the documents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Shape {
+    pub kind: Kind,
+    /// Whether null was seen there too.
+    pub nullable: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Kind {
+    /// Nothing but null, or only ever in empty arrays.
+    #[default]
+    Unknown,
+    Bool,
+    Integer,
+    /// Numbers with a fraction, or integers and such numbers.
+    Float,
+    String,
+    Array(Box<Shape>),
+    Object(ObjectShape),
+    /// Values of kinds that do not go together, such as strings and objects.
+    Mixed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectShape {
+    /// How many objects were seen.
+    pub count: usize,
+    /// The shape of each key, and in how many of the objects it was.
+    pub fields: BTreeMap<String, (Shape, usize)>,
+}
+
+impl ObjectShape {
+    fn is_required(&self, key: &str) -> bool {
+        self.fields
+            .get(key)
+            .is_some_and(|(_, seen)| *seen == self.count)
+    }
+}
+
+impl Shape {
+    pub fn of(value: &Value) -> Shape {
+        let kind = match value {
+            Value::Null => {
+                return Shape {
+                    kind: Kind::Unknown,
+                    nullable: true,
+                }
+            }
+            Value::Bool(_) => Kind::Bool,
+            Value::Number(number) if number.is_f64() => Kind::Float,
+            Value::Number(_) => Kind::Integer,
+            Value::String(_) => Kind::String,
+            Value::Array(items) => Kind::Array(Box::new(infer(items))),
+            Value::Object(object) => Kind::Object(ObjectShape {
+                count: 1,
+                fields: object
+                    .iter()
+                    .map(|(key, value)| (key.clone(), (Shape::of(value), 1)))
+                    .collect(),
+            }),
+        };
+        Shape {
+            kind,
+            nullable: false,
+        }
+    }
+
+    /// The shape of the values seen in either place.
+    pub fn merge(self, other: Shape) -> Shape {
+        let kind = match (self.kind, other.kind) {
+            (Kind::Unknown, kind) | (kind, Kind::Unknown) => kind,
+            (Kind::Integer, Kind::Float) | (Kind::Float, Kind::Integer) => Kind::Float,
+            (Kind::Array(items), Kind::Array(other_items)) => {
+                Kind::Array(Box::new(items.merge(*other_items)))
+            }
+            (Kind::Object(mut object), Kind::Object(other_object)) => {
+                object.count += other_object.count;
+                for (key, (shape, seen)) in other_object.fields {
+                    let field = object.fields.entry(key).or_default();
+                    field.0 = std::mem::take(&mut field.0).merge(shape);
+                    field.1 += seen;
+                }
+                Kind::Object(object)
+            }
+            (kind, other_kind) if kind == other_kind => kind,
+            _ => Kind::Mixed,
+        };
+        Shape {
+            kind,
+            nullable: self.nullable || other.nullable,
+        }
+    }
+}
+
+/// The shape common to all of `documents`.
+pub fn infer<'a>(documents: impl IntoIterator<Item = &'a Value>) -> Shape {
+    documents
+        .into_iter()
+        .map(Shape::of)
+        .fold(Shape::default(), Shape::merge)
+}
+
+/// Rust definitions for values of `shape`, named `name`, with a struct per
+/// kind of object, those it holds first. Keys missing from some objects, or
+/// null in some, become `Option` fields.
+pub fn rust_definitions(shape: &Shape, name: &str) -> String {
+    let mut writer = RustWriter::default();
+    let root = writer.type_of(shape, name);
+    if !matches!(shape.kind, Kind::Object(_)) || shape.nullable {
+        writer
+            .definitions
+            .push(format!("pub type {} = {};\n", camel_case(name), root));
+    }
+    writer.definitions.join("\n")
+}
+
+#[derive(Default)]
+struct RustWriter {
+    definitions: Vec<String>,
+    names: BTreeSet<String>,
+}
+
+impl RustWriter {
+    fn type_of(&mut self, shape: &Shape, name: &str) -> String {
+        let rust_type = match &shape.kind {
+            Kind::Unknown | Kind::Mixed => return "serde_json::Value".to_string(),
+            Kind::Bool => "bool".to_string(),
+            Kind::Integer => "i64".to_string(),
+            Kind::Float => "f64".to_string(),
+            Kind::String => "String".to_string(),
+            Kind::Array(items) => format!("Vec<{}>", self.type_of(items, &singular(name))),
+            Kind::Object(object) => self.define_struct(object, name),
+        };
+        if shape.nullable {
+            format!("Option<{}>", rust_type)
+        } else {
+            rust_type
+        }
+    }
+
+    fn define_struct(&mut self, object: &ObjectShape, name: &str) -> String {
+        let base = camel_case(name);
+        let name = (1..)
+            .map(|number| match number {
+                1 => base.clone(),
+                _ => format!("{}{}", base, number),
+            })
+            .find(|name| !self.names.contains(name))
+            .expect("some name is unused");
+        self.names.insert(name.clone());
+
+        let mut fields = String::new();
+        for (key, (shape, seen)) in &object.fields {
+            let mut field_type = self.type_of(shape, key);
+            if *seen < object.count && !field_type.starts_with("Option<") {
+                field_type = format!("Option<{}>", field_type);
+            }
+            let identifier = identifier(key);
+            if identifier.trim_start_matches("r#") != key {
+                fields.push_str(&format!("    #[serde(rename = {:?})]\n", key));
+            }
+            fields.push_str(&format!("    pub {}: {},\n", identifier, field_type));
+        }
+        self.definitions.push(format!(
+            "#[derive(Deserialize, Serialize, Debug)]\npub struct {} {{\n{}}}\n",
+            name, fields
+        ));
+        name
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let camel: String = name
+        .split(|c: char| !c.is_alphanumeric())
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars))
+                .into_iter()
+                .flatten()
+        })
+        .collect();
+    match camel.chars().next() {
+        None => "Item".to_string(),
+        Some(first) if first.is_numeric() => format!("Item{}", camel),
+        Some(_) => camel,
+    }
+}
+
+// The name of the elements of an array named `name`, such as `sale` for
+// `sales`.
+fn singular(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if name.ends_with('s') && !name.ends_with("ss") {
+        name[..name.len() - 1].to_string()
+    } else {
+        format!("{}_item", name)
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while",
+];
+
+fn identifier(key: &str) -> String {
+    let mut identifier = String::new();
+    for (i, c) in key.chars().enumerate() {
+        if c.is_uppercase() && i > 0 && !identifier.ends_with('_') {
+            identifier.push('_');
+        }
+        if c.is_alphanumeric() {
+            identifier.extend(c.to_lowercase());
+        } else if !identifier.ends_with('_') {
+            identifier.push('_');
+        }
+    }
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_numeric()) {
+        identifier.insert(0, '_');
+    }
+    if KEYWORDS.contains(&identifier.as_str()) {
+        identifier.insert_str(0, "r#");
+    }
+    identifier
+}
+
+/// A JSON Schema (draft 2020-12) accepting values of `shape`.
+pub fn json_schema(shape: &Shape, title: &str) -> Value {
+    let mut schema = Map::new();
+    schema.insert(
+        "$schema".to_string(),
+        "https://json-schema.org/draft/2020-12/schema".into(),
+    );
+    schema.insert("title".to_string(), title.into());
+    if let Value::Object(definition) = schema_of(shape) {
+        schema.extend(definition);
+    }
+    Value::Object(schema)
+}
+
+fn schema_of(shape: &Shape) -> Value {
+    let (type_name, mut schema) = match &shape.kind {
+        Kind::Unknown | Kind::Mixed => return json!({}),
+        Kind::Bool => ("boolean", json!({})),
+        Kind::Integer => ("integer", json!({})),
+        Kind::Float => ("number", json!({})),
+        Kind::String => ("string", json!({})),
+        Kind::Array(items) => ("array", json!({ "items": schema_of(items) })),
+        Kind::Object(object) => {
+            let properties: Map<String, Value> = object
+                .fields
+                .iter()
+                .map(|(key, (shape, _))| (key.clone(), schema_of(shape)))
+                .collect();
+            let required: Vec<&String> = object
+                .fields
+                .keys()
+                .filter(|key| object.is_required(key))
+                .collect();
+            (
+                "object",
+                json!({ "properties": properties, "required": required }),
+            )
+        }
+    };
+    schema["type"] = if shape.nullable {
+        json!([type_name, "null"])
+    } else {
+        json!(type_name)
+    };
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(kind: Kind, nullable: bool) -> Shape {
+        Shape { kind, nullable }
+    }
+
+    // Two documents, in one of which a sale has no unit and a null note.
+    fn documents() -> Vec<Value> {
+        vec![
+            json!({
+                "type": "fruit",
+                "sales": [{ "id": "a", "quantity": 1, "unit": "kg", "note": "x" }]
+            }),
+            json!({
+                "type": "fruit",
+                "sales": [{ "id": "b", "quantity": 1.5, "note": null }]
+            }),
+        ]
+    }
+
+    #[test]
+    fn merges_kinds() {
+        let integer = || shape(Kind::Integer, false);
+        let float = || shape(Kind::Float, false);
+        let string = || shape(Kind::String, false);
+        assert_eq!(integer().merge(float()), float());
+        assert_eq!(integer().merge(integer()), integer());
+        assert_eq!(string().merge(integer()).kind, Kind::Mixed);
+        assert_eq!(Shape::default().merge(string()), string());
+        assert_eq!(
+            Shape::of(&json!([[1], [2.5], []])).kind,
+            Kind::Array(Box::new(shape(Kind::Array(Box::new(float())), false)))
+        );
+    }
+
+    #[test]
+    fn null_makes_a_shape_nullable_without_changing_its_kind() {
+        assert_eq!(infer(&[json!("a"), json!(null)]), shape(Kind::String, true));
+        assert_eq!(infer(&[json!(null)]), shape(Kind::Unknown, true));
+        assert_eq!(infer(&[]), shape(Kind::Unknown, false));
+    }
+
+    #[test]
+    fn counts_the_objects_each_key_is_in() {
+        let shape = infer(&[json!({ "a": 1, "b": 2 }), json!({ "a": 3 })]);
+        let object = match shape.kind {
+            Kind::Object(object) => object,
+            kind => panic!("expected an object, got {:?}", kind),
+        };
+        assert_eq!(object.count, 2);
+        assert_eq!(object.fields["a"].1, 2);
+        assert_eq!(object.fields["b"].1, 1);
+        assert!(object.is_required("a"));
+        assert!(!object.is_required("b"));
+        assert!(!object.is_required("c"));
+    }
+
+    #[test]
+    fn names_types_and_fields() {
+        assert_eq!(singular("sales"), "sale");
+        assert_eq!(singular("categories"), "category");
+        assert_eq!(singular("address"), "address_item");
+        assert_eq!(singular("data"), "data_item");
+        assert_eq!(camel_case("sale_items"), "SaleItems");
+        assert_eq!(camel_case("unit-price"), "UnitPrice");
+        assert_eq!(camel_case("2020"), "Item2020");
+        assert_eq!(camel_case("--"), "Item");
+        assert_eq!(identifier("productId"), "product_id");
+        assert_eq!(identifier("unit price"), "unit_price");
+        assert_eq!(identifier("type"), "r#type");
+        assert_eq!(identifier("2nd"), "_2nd");
+        assert_eq!(identifier(""), "_");
+    }
+
+    #[test]
+    fn writes_rust_definitions() {
+        let shape = infer(&documents());
+        assert_eq!(
+            rust_definitions(&shape, "inventory"),
+            "#[derive(Deserialize, Serialize, Debug)]\n\
+             pub struct Sale {\n    \
+                 pub id: String,\n    \
+                 pub note: Option<String>,\n    \
+                 pub quantity: f64,\n    \
+                 pub unit: Option<String>,\n\
+             }\n\
+             \n\
+             #[derive(Deserialize, Serialize, Debug)]\n\
+             pub struct Inventory {\n    \
+                 pub sales: Vec<Sale>,\n    \
+                 pub r#type: String,\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn renames_fields_and_numbers_structs_of_the_same_name() {
+        let shape = Shape::of(&json!({ "Item Id": 1, "item": { "item": { "a": true } } }));
+        assert_eq!(
+            rust_definitions(&shape, "item"),
+            "#[derive(Deserialize, Serialize, Debug)]\n\
+             pub struct Item3 {\n    \
+                 pub a: bool,\n\
+             }\n\
+             \n\
+             #[derive(Deserialize, Serialize, Debug)]\n\
+             pub struct Item2 {\n    \
+                 pub item: Item3,\n\
+             }\n\
+             \n\
+             #[derive(Deserialize, Serialize, Debug)]\n\
+             pub struct Item {\n    \
+                 #[serde(rename = \"Item Id\")]\n    \
+                 pub item_id: i64,\n    \
+                 pub item: Item2,\n\
+             }\n"
+        );
+        assert_eq!(
+            rust_definitions(&infer(&[json!([1]), json!(null)]), "ids"),
+            "pub type Ids = Option<Vec<i64>>;\n"
+        );
+    }
+
+    #[test]
+    fn writes_a_json_schema() {
+        let shape = infer(&documents());
+        assert_eq!(
+            json_schema(&shape, "inventory"),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "title": "inventory",
+                "type": "object",
+                "properties": {
+                    "sales": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "note": { "type": ["string", "null"] },
+                                "quantity": { "type": "number" },
+                                "unit": { "type": "string" }
+                            },
+                            "required": ["id", "note", "quantity"]
+                        }
+                    },
+                    "type": { "type": "string" }
+                },
+                "required": ["sales", "type"]
+            })
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use serde_derive::Serialize;
+
+use crate::model::Record;
+use crate::{Error, Location};
+
+/// A record left out of a lenient run, and why.
+#[derive(Debug, Serialize)]
+pub struct Reject {
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+impl From<&Error> for Reject {
+    fn from(error: &Error) -> Self {
+        let Location { file, line, column } = error.location().cloned().unwrap_or_default();
+        Reject {
+            file,
+            line,
+            column,
+            field: error.field().map(|field| field.to_string()),
+            reason: error.message(),
+        }
+    }
+}
+
+/// Collects the rejects of a lenient run as JSON lines.
+pub struct RejectLog {
+    sink: Box<dyn Write>,
+    count: usize,
+}
+
+impl RejectLog {
+    pub fn new(sink: impl Write + 'static) -> Self {
+        RejectLog {
+            sink: Box::new(sink),
+            count: 0,
+        }
+    }
+
+    pub fn create(pathname: &str) -> Result<Self, Error> {
+        let file = File::create(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+        Ok(RejectLog::new(BufWriter::new(file)))
+    }
+
+    pub fn reject(&mut self, reject: &Reject) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.sink, reject)?;
+        self.sink.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn finish(mut self) -> Result<usize, Error> {
+        self.sink.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Drops the records that failed on their own from `records`, logging them.
+/// Errors that stop the whole input, such as malformed XML, are passed on.
+pub fn skip_rejects<'a>(
+    records: impl Iterator<Item = Result<Record, Error>> + 'a,
+    log: &'a mut RejectLog,
+) -> impl Iterator<Item = Result<Record, Error>> + 'a {
+    records.filter_map(move |record| match record {
+        Err(e) if e.is_record_error() => log.reject(&Reject::from(&e)).err().map(Err),
+        record => Some(record),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::formats::csv::{CsvOptions, CsvRecords};
+    use crate::formats::in_file;
+
+    // What a reject log wrote, still readable once the log is finished.
+    #[derive(Clone, Default)]
+    struct Written(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Written {
+        fn lines(&self) -> Vec<Value> {
+            self.0
+                .borrow()
+                .split(|byte| *byte == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_slice(line).unwrap())
+                .collect()
+        }
+    }
+
+    const CSV: &str = "record_type,id,product_id,date,quantity,unit,category,name\n\
+                       sale,a,1,1000,2,kg,,\n\
+                       sale,b,1,1000,lots,kg,,\n\
+                       sale,c,1,1000\n\
+                       sale,d,1,1000,3,kg,,\n";
+
+    #[test]
+    fn logs_and_drops_the_records_in_error() {
+        let rows = CsvRecords::mixed(CSV.as_bytes(), &CsvOptions::default()).unwrap();
+        let written = Written::default();
+        let mut log = RejectLog::new(written.clone());
+        let ids: Vec<String> = skip_rejects(in_file(rows, "sales.csv"), &mut log)
+            .map(|record| match record.unwrap() {
+                Record::Sale(sale) => sale.id,
+                Record::Product(product) => product.id.to_string(),
+            })
+            .collect();
+        assert_eq!(ids, ["a", "d"]);
+        assert_eq!(log.finish().unwrap(), 2);
+        let lines = written.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            json!({
+                "file": "sales.csv",
+                "line": 3,
+                "column": null,
+                "field": "quantity",
+                "reason": "invalid value \"lots\" for `quantity`: invalid float literal",
+            })
+        );
+        assert_eq!(lines[1]["line"], 4);
+        assert_eq!(lines[1]["field"], Value::Null);
+    }
+
+    #[test]
+    fn passes_on_errors_that_stop_the_input() {
+        let records = vec![Err(Error::Config("broken".to_string()))];
+        let mut log = RejectLog::new(Written::default());
+        let passed: Vec<_> = skip_rejects(records.into_iter(), &mut log).collect();
+        assert!(matches!(passed[..], [Err(Error::Config(_))]));
+        assert_eq!(log.count(), 0);
+    }
+}
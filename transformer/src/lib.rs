@@ -0,0 +1,17 @@
+pub mod dates;
+pub mod diff;
+pub mod edit;
+pub mod error;
+pub mod formats;
+pub mod infer;
+pub mod lenient;
+pub mod mapping;
+pub mod merge;
+pub mod model;
+pub mod report;
+pub mod schema;
+pub mod units;
+pub mod validate;
+
+pub use error::{Error, Location};
+pub use model::{Product, Record, Sale, SalesAndProducts};
@@ -1,356 +1,518 @@
-// ----------------TOML Section
-
-// this is dynamic approach
-
-// fn main() {
-//     let config_const_values = {
-//         let config_path = std::env::args().nth(1).unwrap();
-
-//         let config_text = std::fs::read_to_string(&config_path).unwrap();
-
-//         config_text.parse::<toml::Value>().unwrap()
-//     };
-
-//     println!("Original: {:#?}", config_const_values);
-
-//     println!(
-//         "[Postgresql].Database: {}",
-//         config_const_values
-//             .get("postgresql")
-//             .unwrap()
-//             .get("database")
-//             .unwrap()
-//             .as_str()
-//             .unwrap()
-//     );
-// }
-
-
-// static approach
-
-// use serde_derive::Deserialize;
-
-// #[allow(unused)]
-// #[derive(Deserialize)]
-// struct Input {
-//     xml_file: String,
-//     json_file: String,
-// }
-
-// #[allow(unused)]
-// #[derive(Deserialize)]
-// struct Redis {
-//     host: String,
-// }
-
-// #[allow(unused)]
-// #[derive(Deserialize)]
-// struct Sqlite {
-//     db_file: String,
-// }
-
-// #[allow(unused)]
-// #[derive(Deserialize)]
-// struct Postgresql {
-//     username: String,
-//     password: String,
-//     host: String,
-//     port: String,
-//     database: String,
-// }
-
-// #[allow(unused)]
-// #[derive(Deserialize)]
-// struct Config {
-//     input: Input,
-//     redis: Redis,
-//     sqlite: Sqlite,
-//     postgresql: Postgresql,
-// }
-
-// fn main() {
-//     let config_const_values: Config = {
-//         let config_path = std::env::args().nth(1).unwrap();
-
-//         let config_text = std::fs::read_to_string(&config_path).unwrap();
-
-//         toml::from_str(&config_text).unwrap()
-//     };
-
-//     println!(
-//         "[postgresql].database: {}",
-//         config_const_values.postgresql.database
-//     )
-// }
-
-//-----------------------JSON SECTION
-//--- dynamic
-// use serde_json::{Number, Value};
-
-// fn main() {
-//     let input_path = std::env::args().nth(1).unwrap();
-//     let output_path = std::env::args().nth(2).unwrap();
-
-//     let mut sales_and_products = {
-//         let sales_and_products_text = std::fs::read_to_string(&input_path).unwrap();
-
-//         serde_json::from_str::<Value>(&sales_and_products_text).unwrap()
-//     };
-
-//     if let Value::Number(n) = &sales_and_products["sales"][1]["quantity"] {
-//         sales_and_products["sales"][1]["quantity"] = 
-//             Value::Number(Number::from_f64(n.as_f64().unwrap() + 1.5).unwrap());
-//     }
-
-//     std::fs::write(
-//         output_path,
-//         serde_json::to_string_pretty(&sales_and_products).unwrap(),
-//     )
-//     .unwrap();
-
-//     println!("{}", "Done!")
-// }
-
-//--- static
-
-// use serde_derive::{Deserialize, Serialize};
-
-// #[derive(Deserialize, Serialize, Debug)]
-// struct Product {
-//     id: i32,
-//     category: String,
-//     name: String,
-// }
-
-// #[derive(Deserialize, Serialize, Debug)]
-// struct Sale {
-//     id: String,
-//     product_id: u32,
-//     date: i64,
-//     quantity: f64,
-//     unit: String,
-// }
-
-// #[derive(Deserialize, Serialize, Debug)]
-// struct SalesAndProducts {
-//     products: Vec<Product>,
-//     sales: Vec<Sale>,
-// }
-
-// fn main() -> Result<(), std::io::Error> {
-//     let input_path = std::env::args().nth(1).unwrap();
-//     let output_path = std::env::args().nth(2).unwrap();
-
-//     let mut sales_and_products = {
-//         let sales_and_products_text = std::fs::read_to_string(&input_path)?;
-
-//         serde_json::from_str::<SalesAndProducts>(&sales_and_products_text).unwrap()
-//     };
-
-//     sales_and_products.sales[1].quantity += 1.5;
-
-//     std::fs::write(
-//         output_path,
-//         serde_json::to_string_pretty(&sales_and_products).unwrap(),
-//     )?;
-//     Ok(())
-// }
-
-//------------------XML
-
-use xml::reader::{EventReader, XmlEvent};
-
-#[derive(Debug, Default)]
-struct Product {
-    id: u32,
-    category: String,
-    name: String,
+mod cli;
+
+use cli::Arguments;
+use serde_derive::Deserialize;
+use transformer::dates::{self, DateOptions};
+use transformer::diff::{self, PatchOperation};
+use transformer::edit::Edit;
+use transformer::formats::{self, Format, FormatOptions};
+use transformer::infer;
+use transformer::lenient::{skip_rejects, RejectLog};
+use transformer::mapping::Mapping;
+use transformer::merge::{self, MergeOptions};
+use transformer::report::{self, GroupBy, ReportFormat};
+use transformer::schema;
+use transformer::units::{Registry, UnitsConfig};
+use transformer::validate::{self, Rules};
+use transformer::{Error, Product, Sale, SalesAndProducts};
+
+const USAGE: &str = "usage: transformer <command> [options]
+
+commands:
+    convert [--from <format>] [--to <format>] [--config <file>] [--schema]
+            [--lenient [--rejects <file>]] <input> <output>
+        Converts a sales and products file between formats.
+        Formats are guessed from the file extensions when not given.
+        With --lenient, records that cannot be read are skipped and listed,
+        as JSON lines, in the rejects file (<output>.rejects.jsonl by default).
+        The [mapping] section of the --config file tells where records and
+        their fields are in XML and JSON input. With --schema, JSON and TOML
+        input is checked against the schema as it is read, listing every
+        mistake.
+
+    edit [--from <format>] [--to <format>] <expression> <input> [<output>]
+        Applies an edit expression to a file, in place unless an output
+        file is given. For example:
+            sales[*].quantity *= 1000
+            sales[?unit == \"g\"].unit = \"kg\"; delete products[-1]
+            rename sales[*].date to sold_at
+        Operations are =, +=, -=, *=, /=, delete and rename; paths select
+        with .key, [\"key\"], [index], [*] and [?path == <JSON value>].
+
+    validate [--from <format>] [--config <file>] <input>
+        Checks that ids are unique, that sales refer to existing products and
+        that quantities are positive, listing every record that does not.
+        The [validation] section of the --config file turns rules off with
+        unique_ids, unique_product_names, product_exists, positive_quantities
+        and known_units = false, and lists accepted units in units.
+        JSON and TOML input is also checked against the schema.
+
+    schema [product | sale | sales-and-products]
+        Prints the JSON Schema of a record, or of a whole file by default.
+
+    report [--from <format>] [--config <file>] [--by <groups>]
+           [--format <format>] [--output <file>] <input>
+        Totals the number and quantity of sales by product, category, unit,
+        day, week or month, the groups given comma-separated, product by
+        default. Sales are always grouped by unit too, so that --units
+        canonical totals all masses together. The report is written as a
+        table, json or csv, to standard output unless --output is given.
+
+    merge [--from <format>] [--to <format>] [--config <file>] [--policy <policy>]
+          [--conflicts <file>] <input>... <output>
+        Merges sales and products files into one, keeping once the products
+        and sales found again with the same id. When such records differ,
+        the policy decides: first (the default) or last keeps that record,
+        fail stops, and keep-both gives the later sale a -2 suffix and the
+        later product a new id. Conflicts are listed, or written as JSON
+        lines to the conflicts file. The [merge] section of the --config
+        file sets the policy too.
+
+    diff [--from <format>] [--config <file>] [--patch <file>] <old> <new>
+        Lists the products and sales added (+), removed (-) and modified (~)
+        from the old file to the new one, matching them by id, with the
+        fields that changed. The --patch file gets a JSON patch (RFC 6902)
+        that turns the old file, read as JSON, into the new one.
+
+    patch [--from <format>] [--to <format>] <patch> <input> [<output>]
+        Applies a JSON patch, such as written by diff, to a file, in place
+        unless an output file is given. A patch written by diff fails on a
+        file whose records are not at the places they had in the old file.
+
+    infer [--from <format>] [--name <name>] [--format <format>] <input>...
+        Infers the keys, types and optional fields common to documents and
+        prints them as Rust definitions of a type called --name (Document by
+        default), or as a JSON Schema with --format json-schema. Keys missing
+        from some objects, or null in some, become Option fields.
+
+formats: toml, json, xml, csv
+
+CSV options:
+    --delimiter <char>           field separator, `,` by default
+    --quote <char>               quoting character, `\"` by default
+    --quote-style <style>        always, necessary, non-numeric or never
+    --header <record.field=name> column header of a field, e.g. sale.product_id=SKU
+    --record-type-column <name>  column telling products from sales in a single file
+A CSV path that is a directory, or ends with `/`, holds products.csv and sales.csv.
+
+XML options:
+    --indent <spaces>            indentation of nested elements, 4 by default,
+                                 or 0 to write the document on one line
+    --encoding <encoding>        utf-8 (the default), or us-ascii to write
+                                 other characters as character references
+
+Unit options:
+    --units <mode>               keep units as written (the default), check
+                                 them against the unit registry, or convert
+                                 quantities to kg, l and u. with canonical
+The [units] section of the --config file sets the mode, adds aliases such as
+\"kilogramme\" = \"kg\" in [units.aliases], and defines units in [[units.custom]]
+with a symbol, a dimension (mass, volume or count) and a factor.
+
+Date options:
+    --dates <format>             write dates as epoch seconds (the default) or iso
+    --time-zone <offset>         time zone of iso dates, UTC or like +02:00
+    --since <date>               only read sales from this date on
+    --until <date>               only read sales before this date
+Dates are read as epoch seconds or milliseconds, or as ISO 8601 such as
+2009-02-13 or 2009-02-13T23:31:30+01:00, UTC unless an offset is given.
+The [dates] section of the --config file takes format, time_zone, since and until.";
+
+// The sections of the --config file that commands use.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Settings {
+    mapping: Mapping,
+    units: UnitsConfig,
+    dates: DateOptions,
+    validation: Rules,
+    merge: MergeOptions,
 }
 
-#[derive(Debug, Default)]
-struct Sale {
-    id: String,
-    product_id: u32,
-    date: i64,
-    quantity: f64,
-    unit: String,
+fn settings(arguments: &Arguments) -> Result<Settings, Error> {
+    match arguments.option("config") {
+        Some(config) => {
+            let settings: Settings = formats::toml::read_toml_file(config)?;
+            settings.mapping.check()?;
+            Ok(settings)
+        }
+        None => Ok(Settings::default()),
+    }
+}
+
+fn format_of(arguments: &Arguments, option: &str, pathname: &str) -> Result<Format, Error> {
+    match arguments.option(option) {
+        Some(name) => name.parse(),
+        None => Format::from_path(pathname).ok_or_else(|| {
+            Error::Usage(format!(
+                "cannot guess the format of {}, use --{}",
+                pathname, option
+            ))
+        }),
+    }
+}
+
+fn single_char(option: &str, value: &str) -> Result<char, Error> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(Error::Usage(format!(
+            "--{} takes a single character",
+            option
+        ))),
+    }
+}
+
+fn date_option(option: &str, value: &str) -> Result<i64, Error> {
+    dates::parse_date(value)
+        .map_err(|reason| Error::Usage(format!("--{} {}: {}", option, value, reason)))
+}
+
+fn format_options(arguments: &Arguments, settings: &Settings) -> Result<FormatOptions, Error> {
+    let mut options = FormatOptions {
+        mapping: settings.mapping.clone(),
+        units: settings.units.clone(),
+        dates: settings.dates.clone(),
+        ..Default::default()
+    };
+    options.check_schema = arguments.switch("schema");
+    if let Some(mode) = arguments.option("units") {
+        options.units.mode = mode.parse()?;
+    }
+    if let Some(format) = arguments.option("dates") {
+        options.dates.format = format.parse()?;
+    }
+    if let Some(time_zone) = arguments.option("time-zone") {
+        options.dates.time_zone = time_zone.parse().map_err(Error::Usage)?;
+    }
+    if let Some(since) = arguments.option("since") {
+        options.dates.since = Some(date_option("since", since)?);
+    }
+    if let Some(until) = arguments.option("until") {
+        options.dates.until = Some(date_option("until", until)?);
+    }
+    if let Some(delimiter) = arguments.option("delimiter") {
+        options.csv.delimiter = single_char("delimiter", delimiter)?;
+    }
+    if let Some(quote) = arguments.option("quote") {
+        options.csv.quote = single_char("quote", quote)?;
+    }
+    if let Some(quote_style) = arguments.option("quote-style") {
+        options.csv.quote_style = quote_style.parse()?;
+    }
+    for header in arguments.options("header") {
+        let (field, name) = header
+            .split_once('=')
+            .ok_or_else(|| Error::Usage(format!("--header {} is not record.field=name", header)))?;
+        options
+            .csv
+            .headers
+            .insert(field.to_string(), name.to_string());
+    }
+    if let Some(column) = arguments.option("record-type-column") {
+        options.csv.record_type_column = column.to_string();
+    }
+    if let Some(indent) = arguments.option("indent") {
+        let spaces: usize = indent
+            .parse()
+            .map_err(|_| Error::Usage(format!("--indent {} is not a number of spaces", indent)))?;
+        options.xml.indent = (spaces > 0).then(|| " ".repeat(spaces));
+    }
+    if let Some(encoding) = arguments.option("encoding") {
+        options.xml.encoding = encoding.parse()?;
+    }
+    Ok(options)
 }
 
-enum LocationItem {
-    Other,
-    InProduct,
-    InSale,
+fn convert(arguments: Arguments) -> Result<(), Error> {
+    let input_path = arguments.positional(0, "input file")?;
+    let output_path = arguments.positional(1, "output file")?;
+    let input_format = format_of(&arguments, "from", input_path)?;
+    let output_format = format_of(&arguments, "to", output_path)?;
+    let options = format_options(&arguments, &settings(&arguments)?)?;
+
+    let mut rejects = None;
+    let sales_and_products = if arguments.switch("lenient") {
+        let rejects_path = arguments
+            .option("rejects")
+            .map(|path| path.to_string())
+            .unwrap_or_else(|| format!("{}.rejects.jsonl", output_path));
+        let mut log = RejectLog::create(&rejects_path)?;
+        let records = formats::read_records(input_format, input_path, &options)?;
+        let sales_and_products =
+            skip_rejects(records, &mut log).collect::<Result<SalesAndProducts, _>>()?;
+        rejects = Some((log.finish()?, rejects_path));
+        sales_and_products
+    } else {
+        formats::read_file(input_format, input_path, &options)?
+    };
+    formats::write_file(output_format, output_path, &sales_and_products, &options)?;
+    println!(
+        "Converted {} products and {} sales.",
+        sales_and_products.products.len(),
+        sales_and_products.sales.len()
+    );
+    if let Some((count, rejects_path)) = rejects {
+        println!("Rejected {} records, listed in {}.", count, rejects_path);
+    }
+    Ok(())
 }
 
-enum LocationProduct {
-    Other,
-    InId,
-    InCategory,
-    InName,
+fn edit(arguments: Arguments) -> Result<(), Error> {
+    let edit: Edit = arguments.positional(0, "edit expression")?.parse()?;
+    let input_path = arguments.positional(1, "input file")?;
+    let output_path = arguments.positional(2, "output file").unwrap_or(input_path);
+    let input_format = format_of(&arguments, "from", input_path)?;
+    let output_format = format_of(&arguments, "to", output_path)?;
+    let options = format_options(&arguments, &settings(&arguments)?)?;
+
+    let mut document = formats::read_value(input_format, input_path, &options)?;
+    let changed = edit.apply(&mut document)?;
+    formats::write_value(output_format, output_path, &document, &options)?;
+    println!("Edited {} values.", changed);
+    Ok(())
 }
 
-enum LocationSale {
-    Other,
-    InId,
-    InProductId,
-    InDate,
-    InQuantity,
-    InUnit,
+fn validate(arguments: Arguments) -> Result<(), Error> {
+    let input_path = arguments.positional(0, "input file")?;
+    let input_format = format_of(&arguments, "from", input_path)?;
+    let settings = settings(&arguments)?;
+    let mut options = format_options(&arguments, &settings)?;
+    options.check_schema = true;
+    let registry = Registry::new(&options.units)?;
+
+    let records = formats::read_records(input_format, input_path, &options)?;
+    let violations = validate::validate_records(records, &settings.validation, &registry)?;
+    if !violations.is_empty() {
+        return Err(Error::Violations(violations));
+    }
+    println!("No violations found in {}.", input_path);
+    Ok(())
+}
+
+fn report(arguments: Arguments) -> Result<(), Error> {
+    let input_path = arguments.positional(0, "input file")?;
+    let input_format = format_of(&arguments, "from", input_path)?;
+    let options = format_options(&arguments, &settings(&arguments)?)?;
+    let groups = arguments
+        .option("by")
+        .unwrap_or("product")
+        .split(',')
+        .map(|group| group.trim().parse())
+        .collect::<Result<Vec<GroupBy>, _>>()?;
+    let report_format = match arguments.option("format") {
+        Some(name) => name.parse()?,
+        None => ReportFormat::Table,
+    };
+
+    let records = formats::read_records(input_format, input_path, &options)?;
+    let report = report::report(records, &groups, &options.dates)?;
+    match arguments.option("output") {
+        Some(output_path) => {
+            let file = std::fs::File::create(output_path)
+                .map_err(|e| Error::from(e).in_file(output_path))?;
+            report.write(std::io::BufWriter::new(file), report_format)
+        }
+        None => report.write(std::io::stdout().lock(), report_format),
+    }
+}
+
+fn merge(arguments: Arguments) -> Result<(), Error> {
+    let (output_path, input_paths) = match arguments.positionals().split_last() {
+        Some((output_path, input_paths)) if !input_paths.is_empty() => (output_path, input_paths),
+        _ => return Err(Error::MissingArgument("input and output files".to_string())),
+    };
+    let output_format = format_of(&arguments, "to", output_path)?;
+    let settings = settings(&arguments)?;
+    let options = format_options(&arguments, &settings)?;
+    let policy = match arguments.option("policy") {
+        Some(name) => name.parse()?,
+        None => settings.merge.policy,
+    };
+
+    let mut inputs = Vec::new();
+    for input_path in input_paths {
+        let input_format = format_of(&arguments, "from", input_path)?;
+        let data = formats::read_file(input_format, input_path, &options)?;
+        inputs.push((input_path.clone(), data));
+    }
+    let (merged, conflicts) = merge::merge(inputs, policy)?;
+    formats::write_file(output_format, output_path, &merged, &options)?;
+    match arguments.option("conflicts") {
+        Some(conflicts_path) => {
+            let file = std::fs::File::create(conflicts_path)
+                .map_err(|e| Error::from(e).in_file(conflicts_path))?;
+            merge::write_conflicts(std::io::BufWriter::new(file), &conflicts)?;
+        }
+        None => {
+            for conflict in &conflicts {
+                println!("{}", conflict);
+            }
+        }
+    }
+    println!(
+        "Merged {} products and {} sales from {} files, with {} conflicts.",
+        merged.products.len(),
+        merged.sales.len(),
+        input_paths.len(),
+        conflicts.len()
+    );
+    Ok(())
+}
+
+fn diff(arguments: Arguments) -> Result<(), Error> {
+    let old_path = arguments.positional(0, "old file")?;
+    let new_path = arguments.positional(1, "new file")?;
+    let options = format_options(&arguments, &settings(&arguments)?)?;
+    let old = formats::read_file(format_of(&arguments, "from", old_path)?, old_path, &options)?;
+    let new = formats::read_file(format_of(&arguments, "from", new_path)?, new_path, &options)?;
+
+    let diff = diff::diff(&old, &new);
+    diff.write_text(std::io::stdout().lock())?;
+    if let Some(patch_path) = arguments.option("patch") {
+        formats::json::write_json_file(patch_path, &diff.patch()?)?;
+    }
+    if diff.is_empty() {
+        println!("No differences between {} and {}.", old_path, new_path);
+    } else {
+        let (added, removed, modified) = diff.counts();
+        println!(
+            "{} added, {} removed and {} modified records.",
+            added, removed, modified
+        );
+    }
+    Ok(())
+}
+
+fn patch(arguments: Arguments) -> Result<(), Error> {
+    let patch_path = arguments.positional(0, "patch file")?;
+    let input_path = arguments.positional(1, "input file")?;
+    let output_path = arguments.positional(2, "output file").unwrap_or(input_path);
+    let input_format = format_of(&arguments, "from", input_path)?;
+    let output_format = format_of(&arguments, "to", output_path)?;
+    let options = format_options(&arguments, &settings(&arguments)?)?;
+
+    let operations: Vec<PatchOperation> = formats::json::read_json_file(patch_path)?;
+    let mut document = formats::read_value(input_format, input_path, &options)?;
+    let applied = diff::apply_patch(&mut document, &operations)?;
+    formats::write_value(output_format, output_path, &document, &options)?;
+    println!("Applied {} patch operations.", applied);
+    Ok(())
+}
+
+fn infer(arguments: Arguments) -> Result<(), Error> {
+    let input_paths = arguments.positionals();
+    if input_paths.is_empty() {
+        return Err(Error::MissingArgument("input file".to_string()));
+    }
+    let name = arguments.option("name").unwrap_or("Document");
+    let options = format_options(&arguments, &settings(&arguments)?)?;
+
+    let mut documents = Vec::new();
+    for input_path in input_paths {
+        let input_format = format_of(&arguments, "from", input_path)?;
+        documents.push(formats::read_value(input_format, input_path, &options)?);
+    }
+    let shape = infer::infer(&documents);
+    match arguments.option("format").unwrap_or("rust") {
+        "rust" => print!("{}", infer::rust_definitions(&shape, name)),
+        "json-schema" => {
+            formats::json::write_json(std::io::stdout().lock(), &infer::json_schema(&shape, name))?;
+            println!();
+        }
+        format => {
+            return Err(Error::Usage(format!(
+                "unknown output format {:?}, expected rust or json-schema",
+                format
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn schema(arguments: Arguments) -> Result<(), Error> {
+    let schema = match arguments
+        .positional(0, "type")
+        .unwrap_or("sales-and-products")
+    {
+        "product" => schema::document_schema::<Product>("Product"),
+        "sale" => schema::document_schema::<Sale>("Sale"),
+        "sales-and-products" => schema::document_schema::<SalesAndProducts>("SalesAndProducts"),
+        name => {
+            return Err(Error::Usage(format!(
+                "no schema for {:?}, expected product, sale or sales-and-products",
+                name
+            )))
+        }
+    };
+    formats::json::write_json(std::io::stdout().lock(), &schema)?;
+    println!();
+    Ok(())
+}
+
+// The options of every command reading files, read by `format_options`.
+const FORMAT_OPTIONS: &[&str] = &[
+    "from",
+    "config",
+    "units",
+    "dates",
+    "time-zone",
+    "since",
+    "until",
+    "delimiter",
+    "quote",
+    "quote-style",
+    "header",
+    "record-type-column",
+    "indent",
+    "encoding",
+];
+
+fn run() -> Result<(), Error> {
+    let mut args = std::env::args().skip(1);
+    let options = |more: &[&'static str]| [FORMAT_OPTIONS, more].concat();
+    match args.next().as_deref() {
+        Some("convert") => convert(Arguments::parse(
+            args,
+            &options(&["to", "rejects"]),
+            &["lenient", "schema"],
+        )?),
+        Some("diff") => diff(Arguments::parse(args, &options(&["patch"]), &[])?),
+        Some("edit") => edit(Arguments::parse(args, &options(&["to"]), &[])?),
+        Some("infer") => infer(Arguments::parse(args, &options(&["name", "format"]), &[])?),
+        Some("merge") => merge(Arguments::parse(
+            args,
+            &options(&["to", "policy", "conflicts"]),
+            &[],
+        )?),
+        Some("patch") => patch(Arguments::parse(args, &options(&["to"]), &[])?),
+        Some("report") => report(Arguments::parse(
+            args,
+            &options(&["by", "format", "output"]),
+            &[],
+        )?),
+        Some("schema") => schema(Arguments::parse(args, &[], &[])?),
+        Some("validate") => validate(Arguments::parse(args, FORMAT_OPTIONS, &[])?),
+        Some(command) => Err(Error::Usage(format!("unknown command {:?}", command))),
+        None => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
 }
 
 fn main() {
-    let mut location_item = LocationItem::Other;
-    let mut location_product = LocationProduct::Other;
-    let mut location_sale = LocationSale::Other;
-    let pathname = std::env::args().nth(1).unwrap();
-    let mut product: Product = Default::default();
-    let mut sale: Sale = Default::default();
-    let file = std::fs::File::open(pathname).unwrap();
-    let file = std::io::BufReader::new(file);
-    let parser = EventReader::new(file);
-    for event in parser {
-        match &location_item {
-            LocationItem::Other => match event {
-                Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "product" => {
-                    location_item = LocationItem::InProduct;
-                    location_product = LocationProduct::Other;
-                    product = Default::default();
-                }
-                Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "sale" => {
-                    location_item = LocationItem::InSale;
-                    location_sale = LocationSale::Other;
-                    sale = Default::default();
-                }
-                _ => {}
-            },
-            LocationItem::InProduct => match &location_product {
-                LocationProduct::Other => match event {
-                    Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "id" => {
-                        location_product = LocationProduct::InId;
-                    }
-                    Ok(XmlEvent::StartElement { ref name, .. })
-                        if name.local_name == "category" =>
-                    {
-                        location_product = LocationProduct::InCategory;
-                    }
-                    Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "name" => {
-                        location_product = LocationProduct::InName;
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_item = LocationItem::Other;
-                        println!("  Exit product: {:?}", product);
-                    }
-                    _ => {}
-                },
-                LocationProduct::InId => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        product.id = characters.parse::<u32>().unwrap();
-                        println!("Got product.id: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_product = LocationProduct::Other;
-                    }
-                    _ => {}
-                },
-                LocationProduct::InCategory => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        product.category = characters.clone();
-                        println!("Got product.category: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_product = LocationProduct::Other;
-                    }
-                    _ => {}
-                },
-                LocationProduct::InName => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        product.name = characters.clone();
-                        println!("Got product.name: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_product = LocationProduct::Other;
-                    }
-                    _ => {}
-                },
-            },
-            LocationItem::InSale => match &location_sale {
-                LocationSale::Other => match event {
-                    Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "id" => {
-                        location_sale = LocationSale::InId;
-                    }
-                    Ok(XmlEvent::StartElement { ref name, .. })
-                        if name.local_name == "product-id" =>
-                    {
-                        location_sale = LocationSale::InProductId;
-                    }
-                    Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "date" => {
-                        location_sale = LocationSale::InDate;
-                    }
-                    Ok(XmlEvent::StartElement { ref name, .. })
-                        if name.local_name == "quantity" =>
-                    {
-                        location_sale = LocationSale::InQuantity;
-                    }
-                    Ok(XmlEvent::StartElement { ref name, .. }) if name.local_name == "unit" => {
-                        location_sale = LocationSale::InUnit;
-                    }
-                    Ok(XmlEvent::EndElement { ref name, .. }) if name.local_name == "sale" => {
-                        location_item = LocationItem::Other;
-                        println!("  Exit sale: {:?}", sale);
-                    }
-                    _ => {}
-                },
-                LocationSale::InId => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        sale.id = characters.clone();
-                        println!("Got sale.id: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_sale = LocationSale::Other;
-                    }
-                    _ => {}
-                },
-                LocationSale::InProductId => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        sale.product_id = characters.parse::<u32>().unwrap();
-                        println!("Got sale.product-id: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_sale = LocationSale::Other;
-                    }
-                    _ => {}
-                },
-                LocationSale::InDate => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        sale.date = characters.parse::<i64>().unwrap();
-                        println!("Got sale.date: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_sale = LocationSale::Other;
-                    }
-                    _ => {}
-                },
-                LocationSale::InQuantity => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        sale.quantity = characters.parse::<f64>().unwrap();
-                        println!("Got sale.quantity: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_sale = LocationSale::Other;
-                    }
-                    _ => {}
-                },
-                LocationSale::InUnit => match event {
-                    Ok(XmlEvent::Characters(characters)) => {
-                        sale.unit = characters.clone();
-                        println!("Got sale.unit: {}.", characters);
-                    }
-                    Ok(XmlEvent::EndElement { .. }) => {
-                        location_sale = LocationSale::Other;
-                    }
-                    _ => {}
-                },
-            },
+    if let Err(e) = run() {
+        if let Error::Violations(violations) = &e {
+            for violation in violations {
+                eprintln!("{}", violation);
+            }
+        }
+        eprintln!("transformer: error: {}", e);
+        if e.is_usage() {
+            eprintln!("Run `transformer` without arguments for usage.");
+            std::process::exit(2);
         }
+        std::process::exit(1);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::formats::xml::{PRODUCT_BINDINGS, PRODUCT_ELEMENT, SALE_BINDINGS, SALE_ELEMENT};
+use crate::model::{Fields, Product, Sale};
+use crate::Error;
+
+/// Where products and sales are found in supplier documents, as set in the
+/// `[mapping]` section of the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Mapping {
+    pub xml: XmlMapping,
+    pub json: JsonMapping,
+}
+
+impl Mapping {
+    pub fn check(&self) -> Result<(), Error> {
+        self.xml.check()?;
+        self.json.check()
+    }
+}
+
+/// Paths are element names separated by `/`. A path ending in `@name` reads
+/// an attribute instead of element text, so `@id` is the `id` attribute of the
+/// record element itself and `details/@code` that of its `<details>` child.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct XmlMapping {
+    /// Path of the elements holding one product. Only its last elements need
+    /// to match, so `item` finds `<catalog><item>` as well.
+    pub product: String,
+    pub sale: String,
+    /// Path of a `Product` field relative to the product element, for the
+    /// fields not read from their usual element.
+    pub product_fields: BTreeMap<String, String>,
+    pub sale_fields: BTreeMap<String, String>,
+}
+
+impl Default for XmlMapping {
+    fn default() -> Self {
+        XmlMapping {
+            product: PRODUCT_ELEMENT.to_string(),
+            sale: SALE_ELEMENT.to_string(),
+            product_fields: BTreeMap::new(),
+            sale_fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl XmlMapping {
+    pub fn product_field_path(&self, field: &str) -> String {
+        field_path(&self.product_fields, field, |field| {
+            default_element(PRODUCT_BINDINGS, field)
+        })
+    }
+
+    pub fn sale_field_path(&self, field: &str) -> String {
+        field_path(&self.sale_fields, field, |field| {
+            default_element(SALE_BINDINGS, field)
+        })
+    }
+
+    pub fn check(&self) -> Result<(), Error> {
+        check_fields::<Product>(&self.product_fields, "mapping.xml.product_fields")?;
+        check_fields::<Sale>(&self.sale_fields, "mapping.xml.sale_fields")
+    }
+}
+
+/// Paths are object keys separated by `/`, so `info/name` reads the `name`
+/// key of the `info` object of a record.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct JsonMapping {
+    /// Top-level key of the products array.
+    pub products: String,
+    pub sales: String,
+    /// Path of a `Product` field within a product object, for the fields not
+    /// read from the key of the same name.
+    pub product_fields: BTreeMap<String, String>,
+    pub sale_fields: BTreeMap<String, String>,
+}
+
+impl Default for JsonMapping {
+    fn default() -> Self {
+        JsonMapping {
+            products: "products".to_string(),
+            sales: "sales".to_string(),
+            product_fields: BTreeMap::new(),
+            sale_fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl JsonMapping {
+    /// Whether records use the field names as keys, so can be deserialized directly.
+    pub fn has_default_fields(&self) -> bool {
+        self.product_fields.is_empty() && self.sale_fields.is_empty()
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == JsonMapping::default()
+    }
+
+    pub fn product_field_path(&self, field: &str) -> String {
+        field_path(&self.product_fields, field, str::to_string)
+    }
+
+    pub fn sale_field_path(&self, field: &str) -> String {
+        field_path(&self.sale_fields, field, str::to_string)
+    }
+
+    pub fn check(&self) -> Result<(), Error> {
+        check_fields::<Product>(&self.product_fields, "mapping.json.product_fields")?;
+        check_fields::<Sale>(&self.sale_fields, "mapping.json.sale_fields")
+    }
+}
+
+fn field_path(
+    overrides: &BTreeMap<String, String>,
+    field: &str,
+    default: impl Fn(&str) -> String,
+) -> String {
+    overrides
+        .get(field)
+        .cloned()
+        .unwrap_or_else(|| default(field))
+}
+
+fn default_element(bindings: &[(&str, &str)], field: &str) -> String {
+    bindings
+        .iter()
+        .find(|(_, bound_field)| *bound_field == field)
+        .map_or(field, |(element, _)| element)
+        .to_string()
+}
+
+fn check_fields<T: Fields>(paths: &BTreeMap<String, String>, section: &str) -> Result<(), Error> {
+    match paths.keys().find(|field| T::field(field).is_none()) {
+        Some(field) => Err(Error::Config(format!(
+            "[{}] names `{}`, which is not a {} field",
+            section,
+            field,
+            T::NAME
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::json::JsonRecords;
+    use crate::formats::xml::XmlRecords;
+    use crate::model::Record;
+
+    fn fields(paths: &[(&str, &str)]) -> BTreeMap<String, String> {
+        paths
+            .iter()
+            .map(|(field, path)| (field.to_string(), path.to_string()))
+            .collect()
+    }
+
+    // A supplier catalog with ids in attributes and names nested.
+    fn xml_mapping() -> XmlMapping {
+        XmlMapping {
+            product: "catalog/item".to_string(),
+            sale: "order".to_string(),
+            product_fields: fields(&[
+                ("id", "@sku"),
+                ("category", "details/@group"),
+                ("name", "details/label"),
+            ]),
+            sale_fields: fields(&[("id", "@number"), ("product_id", "line/@sku")]),
+        }
+    }
+
+    #[test]
+    fn finds_the_path_of_each_field() {
+        let mapping = xml_mapping();
+        assert_eq!(mapping.product_field_path("category"), "details/@group");
+        assert_eq!(mapping.sale_field_path("product_id"), "line/@sku");
+        // Fields not mapped are read from their usual element.
+        assert_eq!(mapping.sale_field_path("date"), "date");
+        assert_eq!(
+            XmlMapping::default().sale_field_path("product_id"),
+            "product-id"
+        );
+        assert_eq!(
+            JsonMapping::default().sale_field_path("product_id"),
+            "product_id"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let mut mapping = Mapping::default();
+        mapping.xml.sale_fields = fields(&[("price", "@price")]);
+        match mapping.check() {
+            Err(Error::Config(message)) => assert_eq!(
+                message,
+                "[mapping.xml.sale_fields] names `price`, which is not a sale field"
+            ),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        mapping.xml = XmlMapping::default();
+        mapping.json.product_fields = fields(&[("colour", "info/colour")]);
+        assert!(mapping.check().is_err());
+    }
+
+    #[test]
+    fn reads_xml_attributes_and_nested_elements() {
+        let document = r#"<export>
+            <catalog>
+                <item sku="7"><details group="fruit"><label>apple</label></details></item>
+            </catalog>
+            <item sku="8"><details group="fruit"><label>pear</label></details></item>
+            <order number="s1">
+                <line sku="7"/>
+                <date>1234567890</date><quantity>2</quantity><unit>kg</unit>
+            </order>
+        </export>"#;
+        let records: Vec<Record> = XmlRecords::with_mapping(document.as_bytes(), &xml_mapping())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // The item outside a catalog is not a product.
+        assert_eq!(
+            records,
+            [
+                Record::Product(Product {
+                    id: 7,
+                    category: "fruit".to_string(),
+                    name: "apple".to_string(),
+                }),
+                Record::Sale(Sale {
+                    id: "s1".to_string(),
+                    product_id: 7,
+                    date: 1_234_567_890,
+                    quantity: 2.0,
+                    unit: "kg".to_string(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_the_path_of_a_missing_xml_value() {
+        let document = r#"<catalog><item><details group="fruit"><label>apple</label></details></item></catalog>"#;
+        let records: Vec<_> = XmlRecords::with_mapping(document.as_bytes(), &xml_mapping())
+            .unwrap()
+            .collect();
+        match &records[..] {
+            [Err(Error::MissingValue { field, path, .. })] => {
+                assert_eq!((*field, path.as_str()), ("id", "@sku"));
+            }
+            other => panic!("expected a missing value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_nested_json_keys() {
+        let mapping = JsonMapping {
+            products: "items".to_string(),
+            sales: "orders".to_string(),
+            product_fields: fields(&[("name", "info/name"), ("category", "info/group")]),
+            sale_fields: BTreeMap::new(),
+        };
+        let document = r#"{
+            "items": [{ "id": 7, "info": { "name": "apple", "group": "fruit" } }],
+            "orders": []
+        }"#;
+        let records: Vec<Record> = JsonRecords::with_mapping(document.as_bytes(), &mapping)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            [Record::Product(Product {
+                id: 7,
+                category: "fruit".to_string(),
+                name: "apple".to_string(),
+            })]
+        );
+    }
+}
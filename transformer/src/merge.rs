@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::model::{differing_fields, Product, Sale, SalesAndProducts};
+use crate::Error;
+
+/// Which record is kept when two with the same id differ.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    #[default]
+    First,
+    Last,
+    /// The merge stops at the first conflict.
+    Fail,
+    /// The later sale gets its id with a `-2` suffix, the later product a
+    /// new id, and its name the suffix if it is the same.
+    KeepBoth,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "first" => Ok(ConflictPolicy::First),
+            "last" => Ok(ConflictPolicy::Last),
+            "fail" => Ok(ConflictPolicy::Fail),
+            "keep-both" => Ok(ConflictPolicy::KeepBoth),
+            _ => Err(Error::Usage(format!(
+                "unknown conflict policy {:?}, expected first, last, fail or keep-both",
+                name
+            ))),
+        }
+    }
+}
+
+/// The `[merge]` section of the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MergeOptions {
+    pub policy: ConflictPolicy,
+}
+
+/// Two records with the same id but different fields, and what was done.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Conflict {
+    /// Such as `sale 2020-7110`.
+    pub record: String,
+    /// The input the record kept so far was read from.
+    pub first: String,
+    /// The input the differing record was read from.
+    pub other: String,
+    pub fields: Vec<&'static str>,
+    pub resolution: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} differs from {} in {}, {}",
+            self.record,
+            self.other,
+            self.first,
+            self.fields.join(", "),
+            self.resolution
+        )
+    }
+}
+
+/// Merges datasets one after the other into a single one, where products
+/// and sales read again with the same id are kept once.
+pub struct Merger {
+    policy: ConflictPolicy,
+    merged: SalesAndProducts,
+    // Index in `merged` and input of every record kept, by id.
+    products: HashMap<i32, (usize, String)>,
+    sales: HashMap<String, (usize, String)>,
+    conflicts: Vec<Conflict>,
+}
+
+impl Merger {
+    pub fn new(policy: ConflictPolicy) -> Self {
+        Merger {
+            policy,
+            merged: SalesAndProducts::default(),
+            products: HashMap::new(),
+            sales: HashMap::new(),
+            conflicts: Vec::new(),
+        }
+    }
+
+    /// Adds the records of one input, named `source` in the conflicts. A
+    /// product given a new id takes the sales of its own input along.
+    pub fn add(&mut self, source: &str, data: SalesAndProducts) -> Result<(), Error> {
+        let mut next_id = self
+            .merged
+            .products
+            .iter()
+            .chain(&data.products)
+            .map(|product| product.id)
+            .max()
+            .unwrap_or(0);
+        let mut renumbered = HashMap::new();
+        for product in data.products {
+            let id = product.id;
+            if let Some(new_id) = self.add_product(source, product, &mut next_id)? {
+                renumbered.insert(id, new_id);
+            }
+        }
+        for mut sale in data.sales {
+            if let Some(new_id) = renumbered.get(&sale.product_id) {
+                sale.product_id = *new_id;
+            }
+            self.add_sale(source, sale)?;
+        }
+        Ok(())
+    }
+
+    // Returns the new id of a product kept under another one.
+    fn add_product(
+        &mut self,
+        source: &str,
+        mut product: Product,
+        next_id: &mut i32,
+    ) -> Result<Option<i32>, Error> {
+        let (index, first) = match self.products.get(&product.id) {
+            Some((index, first)) => (*index, first.clone()),
+            None => {
+                self.keep_product(source, product);
+                return Ok(None);
+            }
+        };
+        let kept = &self.merged.products[index];
+        let fields = differing_fields(kept, &product);
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        let mut conflict = Conflict {
+            record: format!("product {}", product.id),
+            first,
+            other: source.to_string(),
+            fields,
+            resolution: String::new(),
+        };
+        let mut new_id = None;
+        match self.policy {
+            ConflictPolicy::First => conflict.resolution = "kept the first".to_string(),
+            ConflictPolicy::Last => {
+                conflict.resolution = "kept the last".to_string();
+                self.merged.products[index] = product;
+                self.products
+                    .insert(self.merged.products[index].id, (index, source.to_string()));
+            }
+            ConflictPolicy::Fail => return Err(Error::Conflict(Box::new(conflict))),
+            ConflictPolicy::KeepBoth => {
+                *next_id += 1;
+                if product.name == kept.name {
+                    product.name = self.unused_name(&product.name);
+                }
+                conflict.resolution = format!(
+                    "kept both, the last as product {} named {:?}",
+                    next_id, product.name
+                );
+                product.id = *next_id;
+                new_id = Some(*next_id);
+                self.keep_product(source, product);
+            }
+        }
+        self.conflicts.push(conflict);
+        Ok(new_id)
+    }
+
+    fn keep_product(&mut self, source: &str, product: Product) {
+        self.products
+            .insert(product.id, (self.merged.products.len(), source.to_string()));
+        self.merged.products.push(product);
+    }
+
+    fn unused_name(&self, name: &str) -> String {
+        (2..)
+            .map(|suffix| format!("{}-{}", name, suffix))
+            .find(|candidate| {
+                !self
+                    .merged
+                    .products
+                    .iter()
+                    .any(|product| product.name == *candidate)
+            })
+            .expect("some suffix is unused")
+    }
+
+    fn add_sale(&mut self, source: &str, mut sale: Sale) -> Result<(), Error> {
+        let (index, first) = match self.sales.get(&sale.id) {
+            Some((index, first)) => (*index, first.clone()),
+            None => {
+                self.keep_sale(source, sale);
+                return Ok(());
+            }
+        };
+        let fields = differing_fields(&self.merged.sales[index], &sale);
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let mut conflict = Conflict {
+            record: format!("sale {}", sale.id),
+            first,
+            other: source.to_string(),
+            fields,
+            resolution: String::new(),
+        };
+        match self.policy {
+            ConflictPolicy::First => conflict.resolution = "kept the first".to_string(),
+            ConflictPolicy::Last => {
+                conflict.resolution = "kept the last".to_string();
+                self.sales
+                    .insert(sale.id.clone(), (index, source.to_string()));
+                self.merged.sales[index] = sale;
+            }
+            ConflictPolicy::Fail => return Err(Error::Conflict(Box::new(conflict))),
+            ConflictPolicy::KeepBoth => {
+                sale.id = (2..)
+                    .map(|suffix| format!("{}-{}", sale.id, suffix))
+                    .find(|candidate| !self.sales.contains_key(candidate))
+                    .expect("some suffix is unused");
+                conflict.resolution = format!("kept both, the last as sale {}", sale.id);
+                self.keep_sale(source, sale);
+            }
+        }
+        self.conflicts.push(conflict);
+        Ok(())
+    }
+
+    fn keep_sale(&mut self, source: &str, sale: Sale) {
+        self.sales.insert(
+            sale.id.clone(),
+            (self.merged.sales.len(), source.to_string()),
+        );
+        self.merged.sales.push(sale);
+    }
+
+    /// The merged dataset, and the conflicts met on the way.
+    pub fn finish(self) -> (SalesAndProducts, Vec<Conflict>) {
+        (self.merged, self.conflicts)
+    }
+}
+
+/// Merges `inputs`, named by their source, in order.
+pub fn merge(
+    inputs: impl IntoIterator<Item = (String, SalesAndProducts)>,
+    policy: ConflictPolicy,
+) -> Result<(SalesAndProducts, Vec<Conflict>), Error> {
+    let mut merger = Merger::new(policy);
+    for (source, data) in inputs {
+        merger.add(&source, data)?;
+    }
+    Ok(merger.finish())
+}
+
+/// Writes conflicts as JSON lines.
+pub fn write_conflicts<W: Write>(mut sink: W, conflicts: &[Conflict]) -> Result<(), Error> {
+    for conflict in conflicts {
+        serde_json::to_writer(&mut sink, conflict)?;
+        sink.write_all(b"\n")?;
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(id: i32, name: &str) -> Product {
+        Product {
+            id,
+            category: "fruit".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn sale(id: &str, product_id: i32, quantity: f64) -> Sale {
+        Sale {
+            id: id.to_string(),
+            product_id,
+            date: 1_234_567_890,
+            quantity,
+            unit: "kg".to_string(),
+        }
+    }
+
+    fn first() -> SalesAndProducts {
+        SalesAndProducts {
+            products: vec![product(1, "apple"), product(2, "pear")],
+            sales: vec![sale("a", 1, 1.0), sale("b", 2, 2.0)],
+        }
+    }
+
+    // Product 1 and sale a differ from the first input, pear is the same.
+    fn second() -> SalesAndProducts {
+        SalesAndProducts {
+            products: vec![product(1, "green apple"), product(2, "pear")],
+            sales: vec![sale("a", 1, 5.0), sale("b", 2, 2.0), sale("c", 1, 3.0)],
+        }
+    }
+
+    fn merged(
+        policy: ConflictPolicy,
+        inputs: Vec<SalesAndProducts>,
+    ) -> Result<(SalesAndProducts, Vec<Conflict>), Error> {
+        let names = ["first.json", "second.json", "third.json"];
+        merge(
+            names.iter().map(|name| name.to_string()).zip(inputs),
+            policy,
+        )
+    }
+
+    #[test]
+    fn first_keeps_the_records_read_first() {
+        let (data, conflicts) = merged(ConflictPolicy::First, vec![first(), second()]).unwrap();
+        assert_eq!(data.products, first().products);
+        assert_eq!(
+            data.sales,
+            [sale("a", 1, 1.0), sale("b", 2, 2.0), sale("c", 1, 3.0)]
+        );
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "product 1: second.json differs from first.json in name, kept the first",
+                "sale a: second.json differs from first.json in quantity, kept the first"
+            ]
+        );
+    }
+
+    #[test]
+    fn last_keeps_the_records_read_last_in_place() {
+        let (data, conflicts) = merged(ConflictPolicy::Last, vec![first(), second()]).unwrap();
+        assert_eq!(
+            data.products,
+            [product(1, "green apple"), product(2, "pear")]
+        );
+        assert_eq!(
+            data.sales,
+            [sale("a", 1, 5.0), sale("b", 2, 2.0), sale("c", 1, 3.0)]
+        );
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].resolution, "kept the last");
+    }
+
+    #[test]
+    fn last_names_the_input_of_the_record_kept() {
+        let third = SalesAndProducts {
+            products: vec![product(1, "red apple")],
+            sales: Vec::new(),
+        };
+        let (_, conflicts) = merged(ConflictPolicy::Last, vec![first(), second(), third]).unwrap();
+        assert_eq!(conflicts[2].first, "second.json");
+        assert_eq!(conflicts[2].other, "third.json");
+    }
+
+    #[test]
+    fn fail_stops_at_the_first_conflict() {
+        match merged(ConflictPolicy::Fail, vec![first(), second()]) {
+            Err(Error::Conflict(conflict)) => {
+                assert_eq!(conflict.record, "product 1");
+                assert_eq!(conflict.fields, ["name"]);
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert!(merged(ConflictPolicy::Fail, vec![first(), first()]).is_ok());
+    }
+
+    #[test]
+    fn keep_both_renumbers_the_product_and_takes_its_sales_along() {
+        let (data, conflicts) = merged(ConflictPolicy::KeepBoth, vec![first(), second()]).unwrap();
+        // The next id is past those of both inputs.
+        assert_eq!(
+            data.products,
+            [
+                product(1, "apple"),
+                product(2, "pear"),
+                product(3, "green apple")
+            ]
+        );
+        // Sale a keeps its product in the first input, while the sales of the
+        // renumbered product in the second follow it.
+        assert_eq!(
+            data.sales,
+            [
+                sale("a", 1, 1.0),
+                sale("b", 2, 2.0),
+                sale("a-2", 3, 5.0),
+                sale("c", 3, 3.0)
+            ]
+        );
+        assert_eq!(
+            conflicts[0].resolution,
+            "kept both, the last as product 3 named \"green apple\""
+        );
+        assert_eq!(conflicts[1].resolution, "kept both, the last as sale a-2");
+    }
+
+    #[test]
+    fn keep_both_suffixes_names_and_sale_ids_already_taken() {
+        let other_category = |id, name| Product {
+            category: "vegetable".to_string(),
+            ..product(id, name)
+        };
+        let second = SalesAndProducts {
+            products: vec![product(5, "apple-2"), other_category(1, "apple")],
+            sales: vec![sale("a", 5, 2.0)],
+        };
+        let third = SalesAndProducts {
+            products: vec![other_category(1, "apple")],
+            sales: vec![sale("a", 2, 3.0)],
+        };
+        let (data, _) = merged(ConflictPolicy::KeepBoth, vec![first(), second, third]).unwrap();
+        let names: Vec<(i32, &str)> = data
+            .products
+            .iter()
+            .map(|product| (product.id, product.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (1, "apple"),
+                (2, "pear"),
+                (5, "apple-2"),
+                (6, "apple-3"),
+                (7, "apple-4")
+            ]
+        );
+        let sales: Vec<(&str, i32)> = data
+            .sales
+            .iter()
+            .map(|sale| (sale.id.as_str(), sale.product_id))
+            .collect();
+        assert_eq!(sales, [("a", 1), ("b", 2), ("a-2", 5), ("a-3", 2)]);
+    }
+}
@@ -0,0 +1,187 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Product {
+    pub id: i32,
+    pub category: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Sale {
+    pub id: String,
+    pub product_id: i32,
+    /// Seconds since the Unix epoch, read from any form `dates::parse_date` accepts.
+    #[serde(deserialize_with = "crate::dates::deserialize")]
+    pub date: i64,
+    pub quantity: f64,
+    pub unit: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SalesAndProducts {
+    pub products: Vec<Product>,
+    pub sales: Vec<Sale>,
+}
+
+/// One product or sale, as yielded by the streaming readers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Product(Product),
+    Sale(Sale),
+}
+
+impl From<Product> for Record {
+    fn from(product: Product) -> Self {
+        Record::Product(product)
+    }
+}
+
+impl From<Sale> for Record {
+    fn from(sale: Sale) -> Self {
+        Record::Sale(sale)
+    }
+}
+
+impl Extend<Record> for SalesAndProducts {
+    fn extend<I: IntoIterator<Item = Record>>(&mut self, records: I) {
+        for record in records {
+            match record {
+                Record::Product(product) => self.products.push(product),
+                Record::Sale(sale) => self.sales.push(sale),
+            }
+        }
+    }
+}
+
+impl FromIterator<Record> for SalesAndProducts {
+    fn from_iter<I: IntoIterator<Item = Record>>(records: I) -> Self {
+        let mut sales_and_products = SalesAndProducts::default();
+        sales_and_products.extend(records);
+        sales_and_products
+    }
+}
+
+impl IntoIterator for SalesAndProducts {
+    type Item = Record;
+    type IntoIter = std::iter::Chain<
+        std::iter::Map<std::vec::IntoIter<Product>, fn(Product) -> Record>,
+        std::iter::Map<std::vec::IntoIter<Sale>, fn(Sale) -> Record>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.products
+            .into_iter()
+            .map(Record::Product as fn(Product) -> Record)
+            .chain(
+                self.sales
+                    .into_iter()
+                    .map(Record::Sale as fn(Sale) -> Record),
+            )
+    }
+}
+
+/// A named scalar field of a record, read from and written as text.
+pub struct Field<T> {
+    pub name: &'static str,
+    pub get: fn(&T) -> String,
+    pub set: fn(&mut T, &str) -> Result<(), String>,
+}
+
+pub trait Fields: Default + Sized + Into<Record> + 'static {
+    /// Lowercase name of the record kind, such as `product`.
+    const NAME: &'static str;
+    const FIELDS: &'static [Field<Self>];
+    /// Fields holding an epoch, which may be written as a date instead.
+    const DATE_FIELDS: &'static [&'static str] = &[];
+
+    fn field(name: &str) -> Option<&'static Field<Self>> {
+        Self::FIELDS.iter().find(|field| field.name == name)
+    }
+}
+
+/// The names of the fields whose text differs between two records.
+pub fn differing_fields<T: Fields>(first: &T, other: &T) -> Vec<&'static str> {
+    T::FIELDS
+        .iter()
+        .filter(|field| (field.get)(first) != (field.get)(other))
+        .map(|field| field.name)
+        .collect()
+}
+
+impl Fields for Product {
+    const NAME: &'static str = "product";
+    const FIELDS: &'static [Field<Self>] = &[
+        Field {
+            name: "id",
+            get: |product| product.id.to_string(),
+            set: |product, text| {
+                product.id = text.parse().map_err(|e| format!("{}", e))?;
+                Ok(())
+            },
+        },
+        Field {
+            name: "category",
+            get: |product| product.category.clone(),
+            set: |product, text| {
+                product.category = text.to_string();
+                Ok(())
+            },
+        },
+        Field {
+            name: "name",
+            get: |product| product.name.clone(),
+            set: |product, text| {
+                product.name = text.to_string();
+                Ok(())
+            },
+        },
+    ];
+}
+
+impl Fields for Sale {
+    const NAME: &'static str = "sale";
+    const DATE_FIELDS: &'static [&'static str] = &["date"];
+    const FIELDS: &'static [Field<Self>] = &[
+        Field {
+            name: "id",
+            get: |sale| sale.id.clone(),
+            set: |sale, text| {
+                sale.id = text.to_string();
+                Ok(())
+            },
+        },
+        Field {
+            name: "product_id",
+            get: |sale| sale.product_id.to_string(),
+            set: |sale, text| {
+                sale.product_id = text.parse().map_err(|e| format!("{}", e))?;
+                Ok(())
+            },
+        },
+        Field {
+            name: "date",
+            get: |sale| sale.date.to_string(),
+            set: |sale, text| {
+                sale.date = crate::dates::parse_date(text)?;
+                Ok(())
+            },
+        },
+        Field {
+            name: "quantity",
+            get: |sale| sale.quantity.to_string(),
+            set: |sale, text| {
+                sale.quantity = text.parse().map_err(|e| format!("{}", e))?;
+                Ok(())
+            },
+        },
+        Field {
+            name: "unit",
+            get: |sale| sale.unit.clone(),
+            set: |sale, text| {
+                sale.unit = text.to_string();
+                Ok(())
+            },
+        },
+    ];
+}
@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::dates::DateOptions;
+use crate::model::{Product, Record};
+use crate::Error;
+
+/// What sales are totalled by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Product,
+    Category,
+    Unit,
+    Day,
+    /// ISO week, such as `2009-W07`.
+    Week,
+    Month,
+}
+
+impl GroupBy {
+    pub fn name(self) -> &'static str {
+        match self {
+            GroupBy::Product => "product",
+            GroupBy::Category => "category",
+            GroupBy::Unit => "unit",
+            GroupBy::Day => "day",
+            GroupBy::Week => "week",
+            GroupBy::Month => "month",
+        }
+    }
+
+    fn is_period(self) -> bool {
+        matches!(self, GroupBy::Day | GroupBy::Week | GroupBy::Month)
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "product" => Ok(GroupBy::Product),
+            "category" => Ok(GroupBy::Category),
+            "unit" => Ok(GroupBy::Unit),
+            "day" => Ok(GroupBy::Day),
+            "week" => Ok(GroupBy::Week),
+            "month" => Ok(GroupBy::Month),
+            _ => Err(Error::Usage(format!(
+                "cannot group by {:?}, expected product, category, unit, day, week or month",
+                name
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "table" => Ok(ReportFormat::Table),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            _ => Err(Error::Usage(format!(
+                "unknown report format {:?}, expected table, json or csv",
+                name
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Totals {
+    pub sales: usize,
+    pub quantity: f64,
+}
+
+impl Totals {
+    fn add(&mut self, other: Totals) {
+        self.sales += other.sales;
+        self.quantity += other.quantity;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// One value per grouping, in the order of `Report::groups`.
+    pub keys: Vec<String>,
+    pub totals: Totals,
+}
+
+/// Totals of sales, one row per distinct group, sorted by group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub groups: Vec<GroupBy>,
+    pub rows: Vec<Row>,
+}
+
+/// Totals the sales of `records` by `groups`, and always by unit so that
+/// quantities in different units are never added up. Sales are joined to
+/// their product by id; those of unknown products are kept, with an empty
+/// product name and category.
+pub fn report(
+    records: impl Iterator<Item = Result<Record, Error>>,
+    groups: &[GroupBy],
+    dates: &DateOptions,
+) -> Result<Report, Error> {
+    let mut groups = groups.to_vec();
+    if !groups.contains(&GroupBy::Unit) {
+        groups.push(GroupBy::Unit);
+    }
+    let periods: Vec<GroupBy> = groups
+        .iter()
+        .copied()
+        .filter(|group| group.is_period())
+        .collect();
+
+    // Products may come after their sales, so sales are first totalled by
+    // product id and only grouped by name or category once all were read.
+    let mut products: HashMap<i32, Product> = HashMap::new();
+    let mut partial: BTreeMap<(i32, String, Vec<String>), Totals> = BTreeMap::new();
+    for record in records {
+        match record? {
+            Record::Product(product) => {
+                products.insert(product.id, product);
+            }
+            Record::Sale(sale) => {
+                let periods = periods
+                    .iter()
+                    .map(|period| period_of(*period, sale.date, dates))
+                    .collect();
+                partial
+                    .entry((sale.product_id, sale.unit, periods))
+                    .or_default()
+                    .add(Totals {
+                        sales: 1,
+                        quantity: sale.quantity,
+                    });
+            }
+        }
+    }
+
+    let mut rows: BTreeMap<Vec<String>, Totals> = BTreeMap::new();
+    for ((product_id, unit, periods), totals) in partial {
+        let product = products.get(&product_id);
+        let mut periods = periods.into_iter();
+        let keys = groups
+            .iter()
+            .map(|group| match group {
+                GroupBy::Product => product.map_or_else(String::new, |p| p.name.clone()),
+                GroupBy::Category => product.map_or_else(String::new, |p| p.category.clone()),
+                GroupBy::Unit => unit.clone(),
+                GroupBy::Day | GroupBy::Week | GroupBy::Month => periods.next().unwrap_or_default(),
+            })
+            .collect();
+        rows.entry(keys).or_default().add(totals);
+    }
+    Ok(Report {
+        groups,
+        rows: rows
+            .into_iter()
+            .map(|(keys, totals)| Row { keys, totals })
+            .collect(),
+    })
+}
+
+fn period_of(period: GroupBy, epoch: i64, dates: &DateOptions) -> String {
+    let date = match dates.local_date(epoch) {
+        Some(date) => date,
+        None => return epoch.to_string(),
+    };
+    match period {
+        GroupBy::Week => {
+            let (year, week, _) = date.to_iso_week_date();
+            format!("{:04}-W{:02}", year, week)
+        }
+        GroupBy::Month => format!("{:04}-{:02}", date.year(), date.month() as u8),
+        _ => date.to_string(),
+    }
+}
+
+impl Report {
+    pub fn columns(&self) -> Vec<&'static str> {
+        let mut columns: Vec<_> = self.groups.iter().map(|group| group.name()).collect();
+        columns.extend(["sales", "quantity"]);
+        columns
+    }
+
+    fn cells(row: &Row) -> Vec<String> {
+        let mut cells = row.keys.clone();
+        cells.push(row.totals.sales.to_string());
+        cells.push(row.totals.quantity.to_string());
+        cells
+    }
+
+    pub fn write<W: Write>(&self, sink: W, format: ReportFormat) -> Result<(), Error> {
+        match format {
+            ReportFormat::Table => self.write_table(sink),
+            ReportFormat::Json => self.write_json(sink),
+            ReportFormat::Csv => self.write_csv(sink),
+        }
+    }
+
+    /// Columns aligned with spaces, totals to the right.
+    pub fn write_table<W: Write>(&self, mut sink: W) -> Result<(), Error> {
+        let columns = self.columns();
+        let rows: Vec<Vec<String>> = self.rows.iter().map(Self::cells).collect();
+        let widths: Vec<usize> = (0..columns.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|cells| cells[column].chars().count())
+                    .chain([columns[column].len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let totals_from = self.groups.len();
+        let header: Vec<String> = columns.iter().map(|column| column.to_string()).collect();
+        for cells in std::iter::once(&header).chain(&rows) {
+            let line: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    if column < totals_from {
+                        format!("{:<width$}", cell, width = width)
+                    } else {
+                        format!("{:>width$}", cell, width = width)
+                    }
+                })
+                .collect();
+            writeln!(sink, "{}", line.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// An array with an object per row.
+    pub fn write_json<W: Write>(&self, mut sink: W) -> Result<(), Error> {
+        let rows: Vec<Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut object: Map<String, Value> = self
+                    .groups
+                    .iter()
+                    .zip(&row.keys)
+                    .map(|(group, key)| (group.name().to_string(), Value::from(key.as_str())))
+                    .collect();
+                object.insert("sales".to_string(), row.totals.sales.into());
+                object.insert("quantity".to_string(), row.totals.quantity.into());
+                Value::Object(object)
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut sink, &rows)?;
+        writeln!(sink)?;
+        Ok(())
+    }
+
+    pub fn write_csv<W: Write>(&self, sink: W) -> Result<(), Error> {
+        let mut writer = csv::Writer::from_writer(sink);
+        writer.write_record(self.columns())?;
+        for row in &self.rows {
+            writer.write_record(Self::cells(row))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Sale;
+
+    fn product(id: i32, category: &str, name: &str) -> Record {
+        Record::Product(Product {
+            id,
+            category: category.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    // At 2009-02-13T23:31:30Z, a Friday.
+    fn sale(product_id: i32, quantity: f64, unit: &str) -> Record {
+        Record::Sale(Sale {
+            id: format!("{}-{}", product_id, quantity),
+            product_id,
+            date: 1_234_567_890,
+            quantity,
+            unit: unit.to_string(),
+        })
+    }
+
+    // Sales come before the products they are of.
+    fn records() -> Vec<Record> {
+        vec![
+            sale(1, 1.5, "kg"),
+            sale(2, 2.0, "kg"),
+            sale(1, 3.0, "u."),
+            sale(1, 0.5, "kg"),
+            sale(9, 1.0, "kg"),
+            product(1, "fruit", "apple"),
+            product(2, "fruit", "pear"),
+        ]
+    }
+
+    fn report_of(groups: &[GroupBy], dates: &DateOptions) -> Report {
+        report(records().into_iter().map(Ok), groups, dates).unwrap()
+    }
+
+    fn row(keys: &[&str], sales: usize, quantity: f64) -> Row {
+        Row {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            totals: Totals { sales, quantity },
+        }
+    }
+
+    #[test]
+    fn totals_by_product_and_unit() {
+        let report = report_of(&[GroupBy::Product], &DateOptions::default());
+        assert_eq!(report.groups, [GroupBy::Product, GroupBy::Unit]);
+        // The sale of an unknown product is kept, with no name.
+        assert_eq!(
+            report.rows,
+            [
+                row(&["", "kg"], 1, 1.0),
+                row(&["apple", "kg"], 2, 2.0),
+                row(&["apple", "u."], 1, 3.0),
+                row(&["pear", "kg"], 1, 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn totals_by_category_across_products() {
+        let report = report_of(&[GroupBy::Unit, GroupBy::Category], &DateOptions::default());
+        assert_eq!(report.groups, [GroupBy::Unit, GroupBy::Category]);
+        assert_eq!(
+            report.rows,
+            [
+                row(&["kg", ""], 1, 1.0),
+                row(&["kg", "fruit"], 3, 4.0),
+                row(&["u.", "fruit"], 1, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn totals_by_period_in_the_time_zone() {
+        let utc = DateOptions::default();
+        let report = report_of(&[GroupBy::Day, GroupBy::Week, GroupBy::Month], &utc);
+        assert_eq!(
+            report.rows[0].keys,
+            ["2009-02-13", "2009-W07", "2009-02", "kg"]
+        );
+        let east = DateOptions {
+            time_zone: "+02:00".parse().unwrap(),
+            ..DateOptions::default()
+        };
+        let report = report_of(&[GroupBy::Day], &east);
+        assert_eq!(
+            report.rows,
+            [
+                row(&["2009-02-14", "kg"], 4, 5.0),
+                row(&["2009-02-14", "u."], 1, 3.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_each_format() {
+        let report = report_of(&[GroupBy::Product], &DateOptions::default());
+        let written = |format| {
+            let mut output = Vec::new();
+            report.write(&mut output, format).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            written(ReportFormat::Table),
+            "product  unit  sales  quantity\n\
+             \x20        kg        1         1\n\
+             apple    kg        2         2\n\
+             apple    u.        1         3\n\
+             pear     kg        1         2\n"
+        );
+        assert_eq!(
+            written(ReportFormat::Csv),
+            "product,unit,sales,quantity\n,kg,1,1\napple,kg,2,2\napple,u.,1,3\npear,kg,1,2\n"
+        );
+        let json: Value = serde_json::from_str(&written(ReportFormat::Json)).unwrap();
+        assert_eq!(
+            json[1],
+            serde_json::json!({ "product": "apple", "unit": "kg", "sales": 2, "quantity": 2.0 })
+        );
+    }
+}
@@ -0,0 +1,499 @@
+use serde_json::{json, Value};
+
+use crate::dates::DateOptions;
+use crate::formats::csv::CsvOptions;
+use crate::formats::{json, toml, Format};
+use crate::mapping::{JsonMapping, Mapping, XmlMapping};
+use crate::model::{Product, Sale, SalesAndProducts};
+use crate::units::{CustomUnit, UnitsConfig};
+use crate::validate::{Rules, Violation};
+use crate::Error;
+
+/// Types with a JSON Schema (draft 2020-12) for the documents they are read
+/// from, be they JSON or TOML.
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+/// The schema of `T` as a document of its own, named `title`.
+pub fn document_schema<T: JsonSchema>(title: &str) -> Value {
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+    });
+    if let (Value::Object(document), Value::Object(definition)) = (&mut schema, T::json_schema()) {
+        document.extend(definition);
+    }
+    schema
+}
+
+/// An object with all of `required` and maybe more keys, as serde reads records.
+pub fn record(properties: Value, required: &[&str]) -> Value {
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// A configuration section, where unknown keys are rejected rather than
+/// ignored, so that misspelled settings do not go unnoticed.
+pub fn section(properties: Value, required: &[&str]) -> Value {
+    let mut schema = record(properties, required);
+    schema["additionalProperties"] = false.into();
+    schema
+}
+
+pub fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+pub fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn int32() -> Value {
+    json!({ "type": "integer", "minimum": i32::MIN, "maximum": i32::MAX })
+}
+
+fn date() -> Value {
+    json!({
+        "type": ["integer", "string"],
+        "description": "epoch seconds or milliseconds, or an ISO 8601 date",
+    })
+}
+
+fn one_of(names: &[&str]) -> Value {
+    json!({ "type": "string", "enum": names })
+}
+
+fn string_map() -> Value {
+    json!({ "type": "object", "additionalProperties": string() })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+impl JsonSchema for Product {
+    fn json_schema() -> Value {
+        record(
+            json!({ "id": int32(), "category": string(), "name": string() }),
+            &["id", "category", "name"],
+        )
+    }
+}
+
+impl JsonSchema for Sale {
+    fn json_schema() -> Value {
+        record(
+            json!({
+                "id": string(),
+                "product_id": int32(),
+                "date": date(),
+                "quantity": { "type": "number" },
+                "unit": string(),
+            }),
+            &["id", "product_id", "date", "quantity", "unit"],
+        )
+    }
+}
+
+impl JsonSchema for SalesAndProducts {
+    fn json_schema() -> Value {
+        record(
+            json!({
+                "products": array(Product::json_schema()),
+                "sales": array(Sale::json_schema()),
+            }),
+            &["products", "sales"],
+        )
+    }
+}
+
+impl JsonSchema for CsvOptions {
+    fn json_schema() -> Value {
+        let character = json!({ "type": "string", "minLength": 1, "maxLength": 1 });
+        section(
+            json!({
+                "delimiter": character,
+                "quote": character,
+                "quote_style": one_of(&["always", "necessary", "non-numeric", "never"]),
+                "headers": string_map(),
+                "record_type_column": string(),
+            }),
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for XmlMapping {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "product": string(),
+                "sale": string(),
+                "product_fields": string_map(),
+                "sale_fields": string_map(),
+            }),
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for JsonMapping {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "products": string(),
+                "sales": string(),
+                "product_fields": string_map(),
+                "sale_fields": string_map(),
+            }),
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for Mapping {
+    fn json_schema() -> Value {
+        section(
+            json!({ "xml": XmlMapping::json_schema(), "json": JsonMapping::json_schema() }),
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for CustomUnit {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "symbol": string(),
+                "dimension": one_of(&["mass", "volume", "count"]),
+                "factor": { "type": "number", "exclusiveMinimum": 0 },
+            }),
+            &["symbol", "dimension", "factor"],
+        )
+    }
+}
+
+impl JsonSchema for UnitsConfig {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "mode": one_of(&["keep", "check", "canonical"]),
+                "aliases": string_map(),
+                "custom": array(CustomUnit::json_schema()),
+            }),
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for DateOptions {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "format": one_of(&["epoch", "iso"]),
+                "time_zone": string(),
+                "since": date(),
+                "until": date(),
+            }),
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for Rules {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "unique_ids": boolean(),
+                "unique_product_names": boolean(),
+                "product_exists": boolean(),
+                "positive_quantities": boolean(),
+                "known_units": boolean(),
+                "units": array(string()),
+            }),
+            &[],
+        )
+    }
+}
+
+/// Checks `value` against `schema`, listing every violation found, each at
+/// the JSON pointer of the offending value. Only the keywords of the schemas
+/// above are understood.
+pub fn check(schema: &Value, value: &Value) -> Vec<Violation> {
+    check_at_pointer(schema, value, "")
+}
+
+/// Checks a value found at `pointer` in a larger document, such as one
+/// record of a file being streamed.
+pub fn check_at_pointer(schema: &Value, value: &Value, pointer: &str) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    check_at(schema, value, &mut pointer.to_string(), &mut violations);
+    violations
+}
+
+fn check_at(schema: &Value, value: &Value, pointer: &mut String, violations: &mut Vec<Violation>) {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            other => other.as_str().into_iter().collect(),
+        };
+        if !types.iter().any(|name| has_type(value, name)) {
+            violation(
+                violations,
+                pointer,
+                format!("expected {}, found {}", types.join(" or "), type_of(value)),
+            );
+            return;
+        }
+    }
+    if let Some(Value::Array(names)) = schema.get("enum") {
+        if !names.contains(value) {
+            let names: Vec<String> = names.iter().map(Value::to_string).collect();
+            violation(
+                violations,
+                pointer,
+                format!("expected one of {}", names.join(", ")),
+            );
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+        if let Some(minimum) = bound("minimum").filter(|minimum| number < *minimum) {
+            violation(
+                violations,
+                pointer,
+                format!("{} is less than {}", value, minimum),
+            );
+        }
+        if let Some(maximum) = bound("maximum").filter(|maximum| number > *maximum) {
+            violation(
+                violations,
+                pointer,
+                format!("{} is more than {}", value, maximum),
+            );
+        }
+        if let Some(minimum) = bound("exclusiveMinimum").filter(|minimum| number <= *minimum) {
+            violation(
+                violations,
+                pointer,
+                format!("{} is not more than {}", value, minimum),
+            );
+        }
+    }
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count() as u64;
+        let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+        if let Some(minimum) = bound("minLength").filter(|minimum| length < *minimum) {
+            violation(
+                violations,
+                pointer,
+                format!("shorter than {} characters", minimum),
+            );
+        }
+        if let Some(maximum) = bound("maxLength").filter(|maximum| length > *maximum) {
+            violation(
+                violations,
+                pointer,
+                format!("longer than {} characters", maximum),
+            );
+        }
+    }
+
+    match value {
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    with_token(pointer, &index.to_string(), |pointer| {
+                        check_at(item_schema, item, pointer, violations)
+                    });
+                }
+            }
+        }
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        violation(violations, pointer, format!("missing key `{}`", key));
+                    }
+                }
+            }
+            let properties = schema.get("properties");
+            for (key, item) in object {
+                let item_schema = match properties.and_then(|properties| properties.get(key)) {
+                    Some(item_schema) => item_schema,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            with_token(pointer, key, |pointer| {
+                                violation(violations, pointer, "unknown key".to_string())
+                            });
+                            continue;
+                        }
+                        Some(item_schema) => item_schema,
+                        None => continue,
+                    },
+                };
+                with_token(pointer, key, |pointer| {
+                    check_at(item_schema, item, pointer, violations)
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn violation(violations: &mut Vec<Violation>, pointer: &str, message: String) {
+    violations.push(Violation {
+        rule: "schema",
+        record: if pointer.is_empty() {
+            "document".to_string()
+        } else {
+            pointer.to_string()
+        },
+        message,
+    })
+}
+
+// Runs `f` with `token` appended to `pointer`, escaped as RFC 6901 wants.
+fn with_token(pointer: &mut String, token: &str, f: impl FnOnce(&mut String)) {
+    let length = pointer.len();
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+    f(pointer);
+    pointer.truncate(length);
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Checks a JSON or TOML file against the schema of `T` before it is
+/// deserialized, failing with all the violations found, if any.
+pub fn check_file<T: JsonSchema>(format: Format, pathname: &str) -> Result<(), Error> {
+    let document: Value = match format {
+        Format::Json => json::read_json_file(pathname)?,
+        Format::Toml => toml::read_toml_file(pathname)?,
+        Format::Xml | Format::Csv => return Ok(()),
+    };
+    let violations = check(&T::json_schema(), &document);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Violations(violations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The violations as `pointer: message`, in the order they were found.
+    fn violations(schema: Value, value: Value) -> Vec<String> {
+        check(&schema, &value)
+            .iter()
+            .map(|violation| format!("{}: {}", violation.record, violation.message))
+            .collect()
+    }
+
+    #[test]
+    fn points_at_the_value_in_error() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "sales": array(Sale::json_schema()) },
+        });
+        let value = json!({ "sales": [
+            { "id": "s1", "product_id": 1, "date": 1, "quantity": 2.0, "unit": "kg" },
+            { "id": "s2", "product_id": "one", "date": 1, "quantity": 2.0 },
+        ]});
+        assert_eq!(
+            violations(schema, value),
+            [
+                "/sales/1: missing key `unit`",
+                "/sales/1/product_id: expected integer, found string"
+            ]
+        );
+        assert_eq!(
+            violations(json!({ "type": "object" }), json!([])),
+            ["document: expected object, found array"]
+        );
+    }
+
+    #[test]
+    fn escapes_tildes_and_slashes_in_pointers() {
+        let schema = json!({ "additionalProperties": { "type": "integer" } });
+        let value = json!({ "a/b": "x", "c~d": "y", "~1": "z" });
+        let mut found = violations(schema, value);
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                "/a~1b: expected integer, found string",
+                "/c~0d: expected integer, found string",
+                "/~01: expected integer, found string"
+            ]
+        );
+        let found = check_at_pointer(&json!({ "type": "integer" }), &json!(""), "/sales/3");
+        assert_eq!(found[0].record, "/sales/3");
+    }
+
+    #[test]
+    fn rejects_unknown_keys_of_sections() {
+        let schema = section(json!({ "db_file": string() }), &["db_file"]);
+        assert!(violations(schema.clone(), json!({ "db_file": "x" })).is_empty());
+        assert_eq!(
+            violations(schema, json!({ "db_file": "x", "dbfile": "y" })),
+            ["/dbfile: unknown key"]
+        );
+        let record = record(json!({ "id": string() }), &["id"]);
+        assert!(violations(record, json!({ "id": "x", "extra": 1 })).is_empty());
+    }
+
+    #[test]
+    fn checks_exclusive_minimum() {
+        let schema = json!({ "type": "number", "exclusiveMinimum": 0 });
+        assert!(violations(schema.clone(), json!(0.5)).is_empty());
+        assert_eq!(
+            violations(schema.clone(), json!(0)),
+            ["document: 0 is not more than 0"]
+        );
+        assert_eq!(
+            violations(schema, json!(-1.5)),
+            ["document: -1.5 is not more than 0"]
+        );
+    }
+
+    #[test]
+    fn accepts_any_type_of_a_union() {
+        let schema = json!({ "type": ["integer", "string"] });
+        assert!(violations(schema.clone(), json!(1234567890)).is_empty());
+        assert!(violations(schema.clone(), json!("2009-02-13T23:31:30Z")).is_empty());
+        assert_eq!(
+            violations(schema.clone(), json!(1.5)),
+            ["document: expected integer or string, found number"]
+        );
+        assert_eq!(
+            violations(schema, json!(null)),
+            ["document: expected integer or string, found null"]
+        );
+    }
+}
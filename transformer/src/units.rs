@@ -0,0 +1,422 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::model::{Record, Sale};
+use crate::{Error, Location};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Dimension::Mass => "mass",
+            Dimension::Volume => "volume",
+            Dimension::Count => "count",
+        })
+    }
+}
+
+/// What is done with the unit of each sale as it is read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitMode {
+    /// Units are left as written, known or not.
+    #[default]
+    Keep,
+    /// Units are written with their symbol, and unknown units rejected.
+    Check,
+    /// Quantities are also converted to the canonical unit of their dimension.
+    Canonical,
+}
+
+impl FromStr for UnitMode {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "keep" => Ok(UnitMode::Keep),
+            "check" => Ok(UnitMode::Check),
+            "canonical" => Ok(UnitMode::Canonical),
+            _ => Err(Error::Usage(format!(
+                "unknown unit mode {:?}, expected keep, check or canonical",
+                name
+            ))),
+        }
+    }
+}
+
+/// A unit defined in the configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CustomUnit {
+    pub symbol: String,
+    pub dimension: Dimension,
+    /// How many canonical units one of this unit is, e.g. 24 for a box of 24 pieces.
+    pub factor: f64,
+}
+
+/// The `[units]` section of the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UnitsConfig {
+    pub mode: UnitMode,
+    /// More names for known units, such as `"kilogramme" = "kg"`.
+    pub aliases: BTreeMap<String, String>,
+    pub custom: Vec<CustomUnit>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub symbol: String,
+    pub dimension: Dimension,
+    pub factor: f64,
+}
+
+impl Unit {
+    pub fn is_canonical(&self) -> bool {
+        self.factor == 1.0
+    }
+}
+
+// Symbol, dimension, canonical units per unit and other names. The canonical
+// unit of each dimension comes first.
+const BUILTIN_UNITS: &[(&str, Dimension, f64, &[&str])] = &[
+    (
+        "kg",
+        Dimension::Mass,
+        1.0,
+        &["kgs", "kilo", "kilos", "kilogram", "kilograms"],
+    ),
+    ("g", Dimension::Mass, 0.001, &["gr", "gram", "grams"]),
+    (
+        "mg",
+        Dimension::Mass,
+        0.000_001,
+        &["milligram", "milligrams"],
+    ),
+    ("t", Dimension::Mass, 1000.0, &["tonne", "tonnes"]),
+    (
+        "lb",
+        Dimension::Mass,
+        0.453_592_37,
+        &["lbs", "pound", "pounds"],
+    ),
+    (
+        "oz",
+        Dimension::Mass,
+        0.028_349_523_125,
+        &["ounce", "ounces"],
+    ),
+    (
+        "l",
+        Dimension::Volume,
+        1.0,
+        &["lt", "ltr", "liter", "liters", "litre", "litres"],
+    ),
+    (
+        "ml",
+        Dimension::Volume,
+        0.001,
+        &["milliliter", "milliliters", "millilitre", "millilitres"],
+    ),
+    ("cl", Dimension::Volume, 0.01, &["centiliter", "centilitre"]),
+    ("dl", Dimension::Volume, 0.1, &["deciliter", "decilitre"]),
+    (
+        "m3",
+        Dimension::Volume,
+        1000.0,
+        &["m³", "cubic meter", "cubic metre"],
+    ),
+    (
+        "gal",
+        Dimension::Volume,
+        3.785_411_784,
+        &["gallon", "gallons"],
+    ),
+    (
+        "u.",
+        Dimension::Count,
+        1.0,
+        &[
+            "u", "unit", "units", "pc", "pcs", "piece", "pieces", "ea", "each",
+        ],
+    ),
+    ("dozen", Dimension::Count, 12.0, &["dz", "doz"]),
+];
+
+/// The units sales may be in, found by symbol or alias regardless of case.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    units: Vec<Unit>,
+    names: HashMap<String, usize>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry {
+            units: Vec::new(),
+            names: HashMap::new(),
+        };
+        for (symbol, dimension, factor, aliases) in BUILTIN_UNITS {
+            let index = registry.add(symbol, *dimension, *factor);
+            for alias in *aliases {
+                registry.names.insert(alias.to_lowercase(), index);
+            }
+        }
+        registry
+    }
+}
+
+impl Registry {
+    /// The built-in units, with the custom units and aliases of `config`.
+    pub fn new(config: &UnitsConfig) -> Result<Self, Error> {
+        let mut registry = Registry::default();
+        for unit in &config.custom {
+            if !(unit.factor.is_finite() && unit.factor > 0.0) {
+                return Err(Error::Config(format!(
+                    "unit {:?} needs a positive factor",
+                    unit.symbol
+                )));
+            }
+            registry.add(&unit.symbol, unit.dimension, unit.factor);
+        }
+        for (alias, symbol) in &config.aliases {
+            let index = registry.index(symbol).ok_or_else(|| {
+                Error::Config(format!(
+                    "alias {:?} is for unknown unit {:?}",
+                    alias, symbol
+                ))
+            })?;
+            registry.names.insert(alias.to_lowercase(), index);
+        }
+        Ok(registry)
+    }
+
+    fn add(&mut self, symbol: &str, dimension: Dimension, factor: f64) -> usize {
+        let index = self.units.len();
+        self.units.push(Unit {
+            symbol: symbol.to_string(),
+            dimension,
+            factor,
+        });
+        self.names.insert(symbol.to_lowercase(), index);
+        index
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.names.get(&name.trim().to_lowercase()).copied()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Unit> {
+        self.index(name).map(|index| &self.units[index])
+    }
+
+    pub fn canonical(&self, dimension: Dimension) -> &Unit {
+        self.units
+            .iter()
+            .find(|unit| unit.dimension == dimension && unit.is_canonical())
+            .expect("every dimension has a built-in canonical unit")
+    }
+
+    /// Converts a quantity between two units of the same dimension.
+    pub fn convert(&self, quantity: f64, from: &str, to: &str) -> Result<f64, String> {
+        let from = self.find(from).ok_or_else(|| unknown_unit(from))?;
+        let to = self.find(to).ok_or_else(|| unknown_unit(to))?;
+        if from.dimension != to.dimension {
+            return Err(format!(
+                "cannot convert {} ({}) to {} ({})",
+                from.symbol, from.dimension, to.symbol, to.dimension
+            ));
+        }
+        Ok(scale(scale(quantity, from.factor), 1.0 / to.factor))
+    }
+
+    /// Rewrites the unit of a sale, and its quantity too in canonical mode.
+    pub fn apply(&self, sale: &mut Sale, mode: UnitMode) -> Result<(), String> {
+        if mode == UnitMode::Keep {
+            return Ok(());
+        }
+        let unit = self
+            .find(&sale.unit)
+            .ok_or_else(|| "not a known unit".to_string())?;
+        let unit = match mode {
+            UnitMode::Canonical => {
+                sale.quantity = scale(sale.quantity, unit.factor);
+                self.canonical(unit.dimension)
+            }
+            _ => unit,
+        };
+        sale.unit = unit.symbol.clone();
+        Ok(())
+    }
+}
+
+// Dividing by a whole number rather than multiplying by its inverse keeps,
+// say, 0.753 g at exactly 0.000753 kg.
+fn scale(quantity: f64, factor: f64) -> f64 {
+    if factor < 1.0 {
+        quantity / (1.0 / factor)
+    } else {
+        quantity * factor
+    }
+}
+
+fn unknown_unit(name: &str) -> String {
+    format!("unknown unit {:?}", name)
+}
+
+/// Applies `mode` to the sales of `records`, as they are read. Errors are
+/// placed where `location` says the record last read is.
+pub fn apply_units<R>(
+    mut records: R,
+    location: impl Fn(&R) -> Location,
+    registry: Registry,
+    mode: UnitMode,
+) -> impl Iterator<Item = Result<Record, Error>>
+where
+    R: Iterator<Item = Result<Record, Error>>,
+{
+    std::iter::from_fn(move || {
+        let record = records.next()?;
+        Some(record.and_then(|record| match record {
+            Record::Sale(mut sale) => {
+                let unit = sale.unit.clone();
+                registry
+                    .apply(&mut sale, mode)
+                    .map_err(|reason| Error::InvalidValue {
+                        location: location(&records),
+                        field: "unit",
+                        value: unit,
+                        reason,
+                    })?;
+                Ok(Record::Sale(sale))
+            }
+            product => Ok(product),
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::csv::{CsvOptions, CsvRecords};
+
+    fn sale(quantity: f64, unit: &str) -> Sale {
+        Sale {
+            id: "a".to_string(),
+            product_id: 1,
+            date: 1_234_567_890,
+            quantity,
+            unit: unit.to_string(),
+        }
+    }
+
+    fn config() -> UnitsConfig {
+        UnitsConfig {
+            mode: UnitMode::Canonical,
+            aliases: [("kilogramme".to_string(), "kg".to_string())].into(),
+            custom: vec![CustomUnit {
+                symbol: "box".to_string(),
+                dimension: Dimension::Count,
+                factor: 24.0,
+            }],
+        }
+    }
+
+    #[test]
+    fn converts_between_units_of_a_dimension() {
+        let registry = Registry::default();
+        assert_eq!(registry.convert(2.0, "t", "kg"), Ok(2000.0));
+        assert_eq!(registry.convert(1500.0, "ml", "l"), Ok(1.5));
+        assert_eq!(registry.convert(3.0, "dozen", "u."), Ok(36.0));
+        assert_eq!(registry.convert(1.0, "lb", "g"), Ok(453.59237));
+        assert_eq!(
+            registry.convert(1.0, "kg", "l"),
+            Err("cannot convert kg (mass) to l (volume)".to_string())
+        );
+        assert_eq!(
+            registry.convert(1.0, "kg", "stone"),
+            Err("unknown unit \"stone\"".to_string())
+        );
+    }
+
+    #[test]
+    fn small_factors_divide_by_their_inverse() {
+        // Multiplying by 0.001 would give 0.0007530000000000001.
+        assert_eq!(scale(0.753, 0.001), 0.000753);
+        assert_eq!(Registry::default().convert(0.753, "g", "kg"), Ok(0.000753));
+        assert_eq!(scale(0.753, 1000.0), 753.0);
+    }
+
+    #[test]
+    fn finds_units_by_alias_regardless_of_case() {
+        let registry = Registry::default();
+        assert_eq!(registry.find("Kilograms").unwrap().symbol, "kg");
+        assert_eq!(registry.find(" LBS ").unwrap().symbol, "lb");
+        assert_eq!(registry.find("m³").unwrap().symbol, "m3");
+        assert!(registry.find("kilogramme").is_none());
+        assert_eq!(registry.canonical(Dimension::Volume).symbol, "l");
+    }
+
+    #[test]
+    fn adds_the_custom_units_and_aliases_of_the_config() {
+        let registry = Registry::new(&config()).unwrap();
+        assert_eq!(registry.find("KILOGRAMME").unwrap().symbol, "kg");
+        assert_eq!(registry.convert(2.0, "box", "dozen"), Ok(4.0));
+    }
+
+    #[test]
+    fn rejects_bad_custom_units_and_aliases() {
+        let mut config = config();
+        config.custom[0].factor = 0.0;
+        assert!(matches!(Registry::new(&config), Err(Error::Config(_))));
+        let mut config = UnitsConfig::default();
+        config
+            .aliases
+            .insert("stones".to_string(), "stone".to_string());
+        assert!(matches!(Registry::new(&config), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn applies_each_mode() {
+        let registry = Registry::default();
+        let mut kept = sale(750.0, "Grams");
+        registry.apply(&mut kept, UnitMode::Keep).unwrap();
+        assert_eq!(kept, sale(750.0, "Grams"));
+        let mut checked = sale(750.0, "Grams");
+        registry.apply(&mut checked, UnitMode::Check).unwrap();
+        assert_eq!(checked, sale(750.0, "g"));
+        let mut canonical = sale(750.0, "Grams");
+        registry.apply(&mut canonical, UnitMode::Canonical).unwrap();
+        assert_eq!(canonical, sale(0.75, "kg"));
+        let mut unknown = sale(1.0, "furlong");
+        registry.apply(&mut unknown, UnitMode::Keep).unwrap();
+        assert!(registry.apply(&mut unknown, UnitMode::Check).is_err());
+    }
+
+    #[test]
+    fn places_errors_at_the_record() {
+        let csv = "record_type,id,product_id,date,quantity,unit,category,name\n\
+                   sale,a,1,1000,2,kg,,\n\
+                   sale,b,1,1000,2,furlong,,\n";
+        let rows = CsvRecords::mixed(csv.as_bytes(), &CsvOptions::default()).unwrap();
+        let mut records = apply_units(
+            rows,
+            CsvRecords::location,
+            Registry::default(),
+            UnitMode::Check,
+        );
+        assert!(records.next().unwrap().is_ok());
+        let error = records.next().unwrap().unwrap_err();
+        assert_eq!(error.location(), Some(&Location::line(3)));
+        assert!(records.next().is_none());
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::model::{Product, Record, Sale, SalesAndProducts};
+use crate::units::Registry;
+use crate::Error;
+
+/// Checks run over products and sales, as set in the `[validation]` section
+/// of the configuration file. All of them but `units` are on by default.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Rules {
+    pub unique_ids: bool,
+    pub unique_product_names: bool,
+    /// Every sale refers to one of the products.
+    pub product_exists: bool,
+    pub positive_quantities: bool,
+    /// Every sale is in a unit of the registry.
+    pub known_units: bool,
+    /// The units sales may use; any unit is accepted when empty.
+    pub units: Vec<String>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            unique_ids: true,
+            unique_product_names: true,
+            product_exists: true,
+            positive_quantities: true,
+            known_units: true,
+            units: Vec::new(),
+        }
+    }
+}
+
+/// A broken rule, and the record breaking it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub rule: &'static str,
+    /// Such as `sale 2020-7110`, or the JSON pointer of a value breaking the schema.
+    pub record: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.record, self.message, self.rule)
+    }
+}
+
+/// Checks records one at a time, so that a stream can be validated without
+/// keeping more than the ids and names seen so far.
+pub struct Validator<'a> {
+    rules: &'a Rules,
+    registry: &'a Registry,
+    product_ids: HashSet<i32>,
+    product_names: HashMap<String, i32>,
+    sale_ids: HashSet<String>,
+    // Sales whose product had not been read yet, to look up once all were.
+    unresolved: Vec<(String, i32)>,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(rules: &'a Rules, registry: &'a Registry) -> Self {
+        Validator {
+            rules,
+            registry,
+            product_ids: HashSet::new(),
+            product_names: HashMap::new(),
+            sale_ids: HashSet::new(),
+            unresolved: Vec::new(),
+            violations: Vec::new(),
+        }
+    }
+
+    fn violation(&mut self, rule: &'static str, record: String, message: String) {
+        self.violations.push(Violation {
+            rule,
+            record,
+            message,
+        });
+    }
+
+    pub fn check(&mut self, record: &Record) {
+        match record {
+            Record::Product(product) => self.check_product(product),
+            Record::Sale(sale) => self.check_sale(sale),
+        }
+    }
+
+    fn check_product(&mut self, product: &Product) {
+        let record = format!("product {}", product.id);
+        if !self.product_ids.insert(product.id) && self.rules.unique_ids {
+            self.violation(
+                "unique-ids",
+                record.clone(),
+                "id used by an earlier product".to_string(),
+            );
+        }
+        if self.rules.unique_product_names {
+            if let Some(first) = self.product_names.get(&product.name) {
+                let message = format!("name {:?} already used by product {}", product.name, first);
+                self.violation("unique-product-names", record, message);
+            } else {
+                self.product_names.insert(product.name.clone(), product.id);
+            }
+        }
+    }
+
+    fn check_sale(&mut self, sale: &Sale) {
+        let record = format!("sale {}", sale.id);
+        if !self.sale_ids.insert(sale.id.clone()) && self.rules.unique_ids {
+            self.violation(
+                "unique-ids",
+                record.clone(),
+                "id used by an earlier sale".to_string(),
+            );
+        }
+        if self.rules.product_exists && !self.product_ids.contains(&sale.product_id) {
+            self.unresolved.push((sale.id.clone(), sale.product_id));
+        }
+        let positive = sale.quantity.partial_cmp(&0.0) == Some(std::cmp::Ordering::Greater);
+        if self.rules.positive_quantities && !positive {
+            let message = format!("quantity {} is not positive", sale.quantity);
+            self.violation("positive-quantities", record.clone(), message);
+        }
+        if self.rules.known_units && self.registry.find(&sale.unit).is_none() {
+            let message = format!("unit {:?} is not in the registry", sale.unit);
+            self.violation("known-units", record.clone(), message);
+        }
+        if !self.rules.units.is_empty() && !self.rules.units.contains(&sale.unit) {
+            let message = format!("unknown unit {:?}", sale.unit);
+            self.violation("units", record, message);
+        }
+    }
+
+    /// All the violations found, once every record was checked.
+    pub fn finish(mut self) -> Vec<Violation> {
+        for (sale_id, product_id) in std::mem::take(&mut self.unresolved) {
+            if !self.product_ids.contains(&product_id) {
+                let message = format!("product {} does not exist", product_id);
+                self.violation("product-exists", format!("sale {}", sale_id), message);
+            }
+        }
+        self.violations
+    }
+}
+
+pub fn validate(
+    sales_and_products: &SalesAndProducts,
+    rules: &Rules,
+    registry: &Registry,
+) -> Vec<Violation> {
+    let mut validator = Validator::new(rules, registry);
+    for product in &sales_and_products.products {
+        validator.check_product(product);
+    }
+    for sale in &sales_and_products.sales {
+        validator.check_sale(sale);
+    }
+    validator.finish()
+}
+
+/// Validates a stream of records, stopping at the first that cannot be read.
+/// Records that break their schema are listed along with the violations of
+/// the rules.
+pub fn validate_records(
+    records: impl Iterator<Item = Result<Record, Error>>,
+    rules: &Rules,
+    registry: &Registry,
+) -> Result<Vec<Violation>, Error> {
+    let mut validator = Validator::new(rules, registry);
+    let mut violations = Vec::new();
+    for record in records {
+        match record {
+            Ok(record) => validator.check(&record),
+            Err(Error::Violations(found)) => violations.extend(found),
+            Err(e) => return Err(e),
+        }
+    }
+    violations.extend(validator.finish());
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(id: i32, name: &str) -> Product {
+        Product {
+            id,
+            category: "fruit".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn sale(id: &str, product_id: i32, quantity: f64, unit: &str) -> Sale {
+        Sale {
+            id: id.to_string(),
+            product_id,
+            date: 1_234_567_890,
+            quantity,
+            unit: unit.to_string(),
+        }
+    }
+
+    // Breaks each rule but `units`.
+    fn broken() -> SalesAndProducts {
+        SalesAndProducts {
+            products: vec![product(1, "apple"), product(1, "pear"), product(2, "apple")],
+            sales: vec![
+                sale("a", 1, 1.0, "kg"),
+                sale("a", 1, 1.0, "kg"),
+                sale("b", 3, 1.0, "kg"),
+                sale("c", 1, 0.0, "kg"),
+                sale("d", 1, f64::NAN, "kg"),
+                sale("e", 1, 1.0, "furlong"),
+            ],
+        }
+    }
+
+    fn rules_broken(violations: &[Violation]) -> Vec<(&str, &str)> {
+        violations
+            .iter()
+            .map(|violation| (violation.rule, violation.record.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_a_violation_of_each_rule() {
+        let violations = validate(&broken(), &Rules::default(), &Registry::default());
+        assert_eq!(
+            rules_broken(&violations),
+            [
+                ("unique-ids", "product 1"),
+                ("unique-product-names", "product 2"),
+                ("unique-ids", "sale a"),
+                ("positive-quantities", "sale c"),
+                ("positive-quantities", "sale d"),
+                ("known-units", "sale e"),
+                ("product-exists", "sale b"),
+            ]
+        );
+        assert_eq!(
+            violations[1].to_string(),
+            "product 2: name \"apple\" already used by product 1 [unique-product-names]"
+        );
+    }
+
+    #[test]
+    fn skips_the_rules_turned_off() {
+        let rules = Rules {
+            unique_ids: false,
+            unique_product_names: false,
+            product_exists: false,
+            positive_quantities: false,
+            known_units: false,
+            units: vec!["kg".to_string()],
+        };
+        let violations = validate(&broken(), &rules, &Registry::default());
+        assert_eq!(rules_broken(&violations), [("units", "sale e")]);
+    }
+
+    #[test]
+    fn finds_products_read_after_their_sales() {
+        let records = vec![
+            Ok(Record::Sale(sale("a", 1, 1.0, "kg"))),
+            Ok(Record::Product(product(1, "apple"))),
+        ];
+        let violations =
+            validate_records(records.into_iter(), &Rules::default(), &Registry::default()).unwrap();
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn lists_schema_violations_and_stops_at_other_errors() {
+        let schema_violation = Violation {
+            rule: "schema",
+            record: "/sales/0/quantity".to_string(),
+            message: "expected a number".to_string(),
+        };
+        let records = vec![
+            Err(Error::Violations(vec![schema_violation.clone()])),
+            Ok(Record::Sale(sale("a", 2, 1.0, "kg"))),
+        ];
+        let violations =
+            validate_records(records.into_iter(), &Rules::default(), &Registry::default()).unwrap();
+        assert_eq!(violations[0], schema_violation);
+        assert_eq!(
+            rules_broken(&violations[1..]),
+            [("product-exists", "sale a")]
+        );
+        assert_eq!(Error::Violations(violations).to_string(), "2 violations");
+        let records = vec![Err(Error::Config("unreadable".to_string()))];
+        assert!(
+            validate_records(records.into_iter(), &Rules::default(), &Registry::default()).is_err()
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+use transformer::formats::toml::{read_toml, read_toml_file};
+use transformer::Error;
+
+/// Settings used unless a later layer gives others, so that a config file
+/// only needs what differs. Those of a section apply only where the section
+/// is given at all, so that a sink left out of the configuration is not run.
+const DEFAULTS: &str = r#"
+[input.postgresql]
+username = "postgres"
+host = "localhost"
+port = "5432"
+database = "postgres"
+
+[input.redis]
+host = "localhost"
+
+[redis]
+enabled = true
+host = "localhost"
+
+[sqlite]
+enabled = true
+db_file = "sales.sqlite"
+batch_size = 0
+mode = "recreate"
+delete_absent = false
+
+[postgresql]
+enabled = true
+method = "copy"
+mode = "recreate"
+delete_absent = false
+username = "postgres"
+host = "localhost"
+port = "5432"
+database = "postgres"
+"#;
+
+/// Environment variables such as `TRANSFORMER__POSTGRESQL__HOST` set the
+/// key they name, sections separated by double underscores.
+pub const ENV_PREFIX: &str = "TRANSFORMER__";
+
+/// Names the profile when `--profile` is not given.
+pub const PROFILE_VAR: &str = "TRANSFORMER_PROFILE";
+
+/// Where the layers of the configuration are read from.
+#[derive(Debug, Default)]
+pub struct Sources {
+    pub config_file: Option<String>,
+    /// Reads `config.<profile>.toml` next to the config file.
+    pub profile: Option<String>,
+    /// `key=value` overrides, keys being dotted paths like `postgresql.host`.
+    pub sets: Vec<String>,
+}
+
+/// A configuration document built up from layers, each replacing the values
+/// of the ones before, with the layer every value was last set by.
+#[derive(Debug)]
+pub struct Layers {
+    document: Value,
+    sources: BTreeMap<Vec<String>, String>,
+    files: Vec<String>,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            document: Value::Object(Map::new()),
+            sources: BTreeMap::new(),
+            files: Vec::new(),
+        }
+    }
+}
+
+impl Layers {
+    pub fn document(&self) -> &Value {
+        &self.document
+    }
+
+    /// Lays a whole document over the layers so far. Tables are merged key
+    /// by key, while other values, arrays among them, replace earlier ones.
+    pub fn add(&mut self, source: &str, layer: Value) {
+        let mut leaves = Vec::new();
+        leaves_of(&layer, &mut Vec::new(), &mut leaves);
+        for (path, value) in leaves {
+            self.set(source, path, value.clone());
+        }
+    }
+
+    pub fn set(&mut self, source: &str, path: Vec<String>, value: Value) {
+        let (last, parents) = match path.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut table = &mut self.document;
+        for key in parents {
+            if !table.is_object() {
+                *table = Value::Object(Map::new());
+            }
+            table = table
+                .as_object_mut()
+                .expect("just made an object")
+                .entry(key.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+        }
+        if !table.is_object() {
+            *table = Value::Object(Map::new());
+        }
+        table
+            .as_object_mut()
+            .expect("just made an object")
+            .insert(last.clone(), value);
+
+        // Values above or below the new one are gone or replaced.
+        self.sources
+            .retain(|set_path, _| !set_path.starts_with(&path) && !path.starts_with(set_path));
+        self.sources.insert(path, source.to_string());
+    }
+
+    /// Sets the values of `defaults` that no layer gave, in the tables some
+    /// layer did give.
+    pub fn add_defaults(&mut self, defaults: Value) {
+        let mut leaves = Vec::new();
+        leaves_of(&defaults, &mut Vec::new(), &mut leaves);
+        for (path, value) in leaves {
+            let given = |path: &[String]| {
+                path.iter()
+                    .try_fold(&self.document, |table, key| table.get(key))
+                    .is_some()
+            };
+            if given(&path[..path.len() - 1]) && !given(&path) {
+                self.set("default", path, value.clone());
+            }
+        }
+    }
+
+    /// Lays the document read from a file over the layers so far.
+    pub fn add_file(&mut self, pathname: &str, document: Value) {
+        self.add(pathname, document);
+        self.files.push(pathname.to_string());
+    }
+
+    /// The file a value was set in, if it was set by a file.
+    pub fn file_of(&self, path: &[&str]) -> Option<&str> {
+        let path: Vec<String> = path.iter().map(|key| key.to_string()).collect();
+        self.source_of(&path)
+            .filter(|source| self.files.iter().any(|file| file == source))
+    }
+
+    /// The layer a value was set by, or that of the table or array holding it.
+    pub fn source_of(&self, path: &[String]) -> Option<&str> {
+        (0..=path.len())
+            .rev()
+            .find_map(|length| self.sources.get(&path[..length]))
+            .map(String::as_str)
+    }
+}
+
+fn leaves_of<'a>(
+    value: &'a Value,
+    path: &mut Vec<String>,
+    leaves: &mut Vec<(Vec<String>, &'a Value)>,
+) {
+    match value {
+        Value::Object(table) if !table.is_empty() => {
+            for (key, value) in table {
+                path.push(key.clone());
+                leaves_of(value, path, leaves);
+                path.pop();
+            }
+        }
+        // An empty table is kept, as it still says the section is there.
+        _ => leaves.push((path.clone(), value)),
+    }
+}
+
+/// Reads every layer in turn: config file, profile file, environment and
+/// `--set` options, then the defaults. Values given as text, in the environment
+/// or with `--set`, are typed after `schema`.
+pub fn load(sources: &Sources, schema: &Value) -> Result<Layers, Error> {
+    let profile = sources
+        .profile
+        .clone()
+        .or_else(|| std::env::var(PROFILE_VAR).ok());
+    let mut files = Vec::new();
+    match (&sources.config_file, profile) {
+        (Some(config_file), profile) => {
+            files.push((config_file.clone(), read_toml_file(config_file)?));
+            if let Some(profile) = profile {
+                let profile_file = profile_file(config_file, &profile);
+                let document = read_toml_file(&profile_file)?;
+                files.push((profile_file, document));
+            }
+        }
+        (None, Some(profile)) => {
+            return Err(Error::Usage(format!(
+                "profile {:?} needs a config file to be next to",
+                profile
+            )))
+        }
+        (None, None) => {}
+    }
+    let variables = std::env::vars().filter(|(name, _)| name.starts_with(ENV_PREFIX));
+    layer(files, variables, &sources.sets, schema)
+}
+
+// Lays the documents of the files, then the environment variables, then the
+// `--set` options over each other, and fills in the defaults.
+fn layer(
+    files: Vec<(String, Value)>,
+    variables: impl Iterator<Item = (String, String)>,
+    sets: &[String],
+    schema: &Value,
+) -> Result<Layers, Error> {
+    let mut layers = Layers::default();
+    for (pathname, document) in files {
+        layers.add_file(&pathname, document);
+    }
+
+    let mut variables: Vec<(String, String)> = variables.collect();
+    variables.sort();
+    for (name, text) in variables {
+        let path: Vec<String> = name[ENV_PREFIX.len()..]
+            .split("__")
+            .map(str::to_lowercase)
+            .collect();
+        let value = typed_value(schema, &path, &text);
+        layers.set(&format!("environment {}", name), path, value);
+    }
+
+    for set in sets {
+        let (key, text) = set
+            .split_once('=')
+            .ok_or_else(|| Error::Usage(format!("--set {} is not key=value", set)))?;
+        let path: Vec<String> = key.split('.').map(str::to_string).collect();
+        let value = typed_value(schema, &path, text);
+        layers.set(&format!("--set {}", key), path, value);
+    }
+
+    layers.add_defaults(read_toml(DEFAULTS.as_bytes())?);
+    Ok(layers)
+}
+
+/// `config.prod.toml` for the `prod` profile of `config.toml`.
+pub fn profile_file(config_file: &str, profile: &str) -> String {
+    let path = Path::new(config_file);
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("config");
+    path.with_file_name(format!("{}.{}.toml", stem, profile))
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Keeps text as a string where the schema takes one, and reads it as JSON
+// otherwise, so that `5432` can be a port string and `false` a flag.
+fn typed_value(schema: &Value, path: &[String], text: &str) -> Value {
+    let schema = path.iter().try_fold(schema, |schema, key| {
+        schema
+            .get("properties")
+            .and_then(|properties| properties.get(key))
+            .or_else(|| schema.get("additionalProperties").filter(|s| s.is_object()))
+    });
+    let takes_string = match schema.and_then(|schema| schema.get("type")) {
+        Some(Value::String(name)) => name == "string",
+        Some(Value::Array(names)) => names.iter().any(|name| name == "string"),
+        _ => false,
+    };
+    if takes_string {
+        return Value::String(text.to_string());
+    }
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+/// The settings of `config`, one `key = value` line each, with the layer
+/// that set them. Settings no layer gave are the built-in defaults.
+pub fn show(layers: &Layers, config: &Value) -> String {
+    let mut leaves = Vec::new();
+    leaves_of(config, &mut Vec::new(), &mut leaves);
+    let lines: Vec<(String, &str)> = leaves
+        .into_iter()
+        .filter(|(_, value)| match value {
+            Value::Null => false,
+            Value::Object(table) => !table.is_empty(),
+            _ => true,
+        })
+        .map(|(path, value)| {
+            let keys: Vec<String> = path.iter().map(|key| toml_key(key)).collect();
+            let line = format!("{} = {}", keys.join("."), value);
+            (line, layers.source_of(&path).unwrap_or("default"))
+        })
+        .collect();
+    let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+    lines
+        .iter()
+        .map(|(line, source)| format!("{:<width$}  # {}\n", line, source, width = width))
+        .collect()
+}
+
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        Value::from(key).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use transformer::schema::JsonSchema;
+
+    use super::*;
+    use crate::sinks::postgresql::Postgresql;
+    use crate::sinks::sqlite::Sqlite;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "sqlite": Sqlite::json_schema(),
+                "postgresql": Postgresql::json_schema(),
+            },
+        })
+    }
+
+    fn file(pathname: &str, text: &str) -> (String, Value) {
+        (pathname.to_string(), read_toml(text.as_bytes()).unwrap())
+    }
+
+    fn variable(name: &str, text: &str) -> (String, String) {
+        (name.to_string(), text.to_string())
+    }
+
+    fn value_and_source<'a>(layers: &'a Layers, path: &[&str]) -> (&'a Value, &'a str) {
+        let path: Vec<String> = path.iter().map(|key| key.to_string()).collect();
+        let value = path
+            .iter()
+            .try_fold(layers.document(), |table, key| table.get(key))
+            .unwrap();
+        (value, layers.source_of(&path).unwrap())
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let files = vec![
+            file(
+                "config.toml",
+                "[postgresql]\nhost = \"file\"\nport = \"1\"\ndatabase = \"file\"\nusername = \"file\"",
+            ),
+            file(
+                "config.prod.toml",
+                "[postgresql]\nport = \"2\"\ndatabase = \"profile\"\nusername = \"profile\"",
+            ),
+        ];
+        let variables = vec![
+            variable("TRANSFORMER__POSTGRESQL__DATABASE", "environment"),
+            variable("TRANSFORMER__POSTGRESQL__USERNAME", "environment"),
+        ];
+        let sets = ["postgresql.username=set".to_string()];
+        let layers = layer(files, variables.into_iter(), &sets, &schema()).unwrap();
+        let value = |key| value_and_source(&layers, &["postgresql", key]);
+        assert_eq!(value("method"), (&json!("copy"), "default"));
+        assert_eq!(value("host"), (&json!("file"), "config.toml"));
+        assert_eq!(value("port"), (&json!("2"), "config.prod.toml"));
+        assert_eq!(
+            value("database"),
+            (
+                &json!("environment"),
+                "environment TRANSFORMER__POSTGRESQL__DATABASE"
+            )
+        );
+        assert_eq!(
+            value("username"),
+            (&json!("set"), "--set postgresql.username")
+        );
+        assert_eq!(
+            layers.file_of(&["postgresql", "port"]),
+            Some("config.prod.toml")
+        );
+        assert_eq!(layers.file_of(&["postgresql", "username"]), None);
+    }
+
+    #[test]
+    fn defaults_fill_only_given_sections() {
+        let files = vec![file("config.toml", "[sqlite]\ndb_file = \"s.sqlite\"")];
+        let layers = layer(files, std::iter::empty(), &[], &schema()).unwrap();
+        assert_eq!(
+            value_and_source(&layers, &["sqlite", "mode"]),
+            (&json!("recreate"), "default")
+        );
+        assert!(layers.document().get("postgresql").is_none());
+        assert!(layers.document().get("redis").is_none());
+    }
+
+    #[test]
+    fn types_text_after_the_schema() {
+        let schema = schema();
+        let typed = |path: &str, text: &str| {
+            let path: Vec<String> = path.split('.').map(str::to_string).collect();
+            typed_value(&schema, &path, text)
+        };
+        assert_eq!(typed("postgresql.port", "5432"), json!("5432"));
+        assert_eq!(typed("postgresql.enabled", "false"), json!(false));
+        assert_eq!(typed("sqlite.batch_size", "1000"), json!(1000));
+        assert_eq!(typed("sqlite.mode", "upsert"), json!("upsert"));
+        assert_eq!(typed("sqlite.unknown", "[1, 2]"), json!([1, 2]));
+        assert_eq!(typed("sqlite.batch_size", "many"), json!("many"));
+    }
+
+    #[test]
+    fn rejects_a_set_without_a_value() {
+        let sets = ["postgresql.host".to_string()];
+        let error = layer(Vec::new(), std::iter::empty(), &sets, &schema()).unwrap_err();
+        assert!(error.is_usage());
+    }
+}
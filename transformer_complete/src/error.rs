@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// What can go wrong moving records between the input and the stores.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the input, or settings that do not hold.
+    Transformer(transformer::Error),
+    Sqlite(rusqlite::Error),
+    Postgresql(postgres::Error),
+    Redis(redis::RedisError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Transformer(error) => write!(f, "{}", error),
+            Error::Sqlite(error) => write!(f, "SQLite error: {}", error),
+            Error::Postgresql(error) => write!(f, "PostgreSQL error: {}", error),
+            Error::Redis(error) => write!(f, "Redis error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<transformer::Error> for Error {
+    fn from(error: transformer::Error) -> Self {
+        Error::Transformer(error)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::Sqlite(error)
+    }
+}
+
+impl From<postgres::Error> for Error {
+    fn from(error: postgres::Error) -> Self {
+        Error::Postgresql(error)
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(error: redis::RedisError) -> Self {
+        Error::Redis(error)
+    }
+}
@@ -1,292 +1,344 @@
-use redis::Commands;
+mod config;
+mod error;
+mod secret;
+mod sinks;
+mod sources;
+
+use std::time::Instant;
+
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use transformer::dates::DateOptions;
+use transformer::formats::csv::CsvOptions;
+use transformer::formats::{Format, FormatOptions};
+use transformer::mapping::Mapping;
+use transformer::schema::{self, section, string, JsonSchema};
+use transformer::units::{Registry, UnitsConfig};
+use transformer::validate::{validate_records, Rules};
 
+use error::Error;
+use sinks::postgresql::{Postgresql, PostgresqlSink, Server as PostgresqlServer};
+use sinks::redis::{Redis, RedisSink, Server as RedisServer};
+use sinks::sqlite::{Sqlite, SqliteSink};
+use sources::postgresql::PostgresqlSource;
+use sources::redis::RedisSource;
+use sources::sqlite::SqliteSource;
+use sources::{FileSource, Source};
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct Input {
-    json_file: String,
-}
+const USAGE: &str = "usage: transformer_complete [config show] [--profile <name>]
+                            [--set <key>=<value>]... [--only <sinks>]
+                            [<config file>]
+       transformer_complete --schema
 
-#[allow(unused)]
-#[derive(Debug,Deserialize)]
-struct Redis {
-    host: String,
-}
+Reads sales and products from the input, a JSON, XML or CSV file or an
+SQLite, PostgreSQL or Redis store, and loads them into SQLite, PostgreSQL and
+Redis, each of them if its section is given and not `enabled = false`, or
+only the comma-separated sinks given by --only, such as --only sqlite. A sink
+failing does not stop the others, but makes the exit status 1.
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct Sqlite {
-    db_file: String,
-}
+Settings are read in layers, each overriding the ones before: built-in
+defaults, the config file, the profile file next to it (config.prod.toml for
+config.toml and the prod profile, given by --profile or TRANSFORMER_PROFILE),
+environment variables such as TRANSFORMER__POSTGRESQL__HOST, and --set
+options such as postgresql.host=db1. `config show` prints the resulting
+settings, each with the layer it comes from. --schema prints the JSON Schema
+of the settings.
+
+The PostgreSQL password is given by one of password, password_file,
+password_env and password_command. A file holding it must not be readable
+by anyone but its owner and group.";
 
 #[allow(unused)]
-#[derive(Debug, Deserialize)]
-struct Postgresql {
-    username: String,
-    password: String,
-    host: String,
-    port: String,
-    database: String,
+#[derive(Debug, Deserialize, Serialize)]
+struct Input {
+    json_file: Option<String>,
+    xml_file: Option<String>,
+    /// A CSV file with a record type column, or a directory holding
+    /// `products.csv` and `sales.csv`.
+    csv_file: Option<String>,
+    /// A database written by an SQLite sink.
+    sqlite_file: Option<String>,
+    postgresql: Option<PostgresqlServer>,
+    redis: Option<RedisServer>,
+    #[serde(default)]
+    csv: CsvOptions,
 }
 
 #[allow(unused)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct Config {
     input: Input,
-    redis: Redis,
-    sqlite: Sqlite,
-    postgresql: Postgresql,
+    /// The sinks, each run if its section is given and it is enabled.
+    redis: Option<Redis>,
+    sqlite: Option<Sqlite>,
+    postgresql: Option<Postgresql>,
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default)]
+    units: UnitsConfig,
+    /// Only sales dated within `since` and `until` are loaded from files.
+    #[serde(default)]
+    dates: DateOptions,
+    #[serde(default)]
+    validation: Rules,
 }
 
-#[derive(Deserialize, Serialize, Debug, Default)]
-struct Product {
-    id: i32,
-    category: String,
-    name: String,
+impl JsonSchema for Input {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "json_file": string(),
+                "xml_file": string(),
+                "csv_file": string(),
+                "sqlite_file": string(),
+                "postgresql": PostgresqlServer::json_schema(),
+                "redis": RedisServer::json_schema(),
+                "csv": CsvOptions::json_schema(),
+            }),
+            &[],
+        )
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug, Default)]
-struct Sale {
-    id: String,
-    product_id: i32,
-    date: i64,
-    quantity: f64,
-    unit: String,
+impl JsonSchema for Config {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "input": Input::json_schema(),
+                "redis": Redis::json_schema(),
+                "sqlite": Sqlite::json_schema(),
+                "postgresql": Postgresql::json_schema(),
+                "mapping": Mapping::json_schema(),
+                "units": UnitsConfig::json_schema(),
+                "dates": DateOptions::json_schema(),
+                "validation": Rules::json_schema(),
+            }),
+            &["input"],
+        )
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-struct SalesAndProducts {
-    products: Vec<Product>,
-    sales: Vec<Sale>,
+// Opens the single input. Files are read as a stream of records, so that
+// inputs of any size can be loaded.
+fn open_source(config: &Config) -> Result<Box<dyn Source>, Error> {
+    let input = &config.input;
+    let options = FormatOptions {
+        csv: input.csv.clone(),
+        mapping: config.mapping.clone(),
+        units: config.units.clone(),
+        dates: config.dates.clone(),
+        check_schema: true,
+        ..Default::default()
+    };
+    let files = [
+        (Format::Json, &input.json_file),
+        (Format::Xml, &input.xml_file),
+        (Format::Csv, &input.csv_file),
+    ];
+    let given = files.iter().filter(|(_, file)| file.is_some()).count()
+        + [
+            input.sqlite_file.is_some(),
+            input.postgresql.is_some(),
+            input.redis.is_some(),
+        ]
+        .iter()
+        .filter(|given| **given)
+        .count();
+    if given != 1 {
+        return Err(transformer::Error::Config(
+            "[input] needs exactly one of json_file, xml_file, csv_file, sqlite_file, \
+             postgresql and redis"
+                .to_string(),
+        )
+        .into());
+    }
+    if let Some((format, Some(file))) = files.into_iter().find(|(_, file)| file.is_some()) {
+        return Ok(Box::new(FileSource::new(format, file, options)));
+    }
+    Ok(
+        match (&input.sqlite_file, &input.postgresql, &input.redis) {
+            (Some(db_file), _, _) => Box::new(SqliteSource::open(db_file)?),
+            (_, Some(server), _) => Box::new(PostgresqlSource::open(server)?),
+            (_, _, Some(server)) => Box::new(RedisSource::open(server)?),
+            _ => unreachable!("one input is given"),
+        },
+    )
 }
 
-fn read_json_file(pathname: &str) -> SalesAndProducts {
-    serde_json::from_str::<SalesAndProducts>(&std::fs::read_to_string(&pathname).unwrap()).unwrap()
+/// The sinks with a section in `config`, which are opened only if chosen.
+fn sink_registry(config: &Config) -> sinks::Registry<'_> {
+    let mut registry = sinks::Registry::default();
+    if let Some(sqlite) = &config.sqlite {
+        registry.add("sqlite", "SQLite", sqlite.enabled, move || {
+            Ok(Box::new(SqliteSink::open(sqlite)?))
+        });
+    }
+    if let Some(postgresql) = &config.postgresql {
+        registry.add("postgresql", "PostgreSQL", postgresql.enabled, move || {
+            Ok(Box::new(PostgresqlSink::open(postgresql)?))
+        });
+    }
+    if let Some(redis) = &config.redis {
+        registry.add("redis", "Redis", redis.enabled, move || {
+            Ok(Box::new(RedisSink::open(&redis.server)?))
+        });
+    }
+    registry
 }
 
-fn recreate_sqlite_db(sqlite_config: &Sqlite) -> rusqlite::Result<rusqlite::Connection> {
-    use rusqlite::{params, Connection};
-    let conn = Connection::open(&sqlite_config.db_file)?;
-    let _ = conn.execute("DROP TABLE Sales", params![]);
-    let _ = conn.execute("DROP TABLE Products", params![]);
-    conn.execute(
-        "CREATE TABLE Products (
-            id INTEGER PRIMARY KEY,
-            category TEXT NOT NULL,
-            name TEXT NOT NULL UNIQUE)",
-        params![],
-    )?;
-    conn.execute(
-        "CREATE TABLE Sales (
-            id TEXT PRIMARY KEY,
-            product_id INTEGER NOT NULL REFERENCES Products,
-            sale_date BIGINT NOT NULL,
-            quantity DOUBLE PRECISION NOT NULL,
-            unit TEXT NOT NULL)",
-        params![],
-    )?;
-    Ok(conn)
+struct Arguments {
+    show: bool,
+    schema: bool,
+    only: Vec<String>,
+    sources: config::Sources,
 }
 
-fn write_into_sqlite_db(
-    conn: &rusqlite::Connection,
-    sales_and_products: &SalesAndProducts,
-) -> rusqlite::Result<()> {
-    use rusqlite::params;
-    for product in &sales_and_products.products {
-        conn.execute(
-            "INSERT INTO Products (
-            id, category, name
-            ) VALUES ($1, $2, $3)",
-            params![product.id, product.category, product.name],
-        )?;
+fn parse_arguments(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Arguments, transformer::Error> {
+    let mut arguments = Arguments {
+        show: false,
+        schema: false,
+        only: Vec::new(),
+        sources: config::Sources::default(),
+    };
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| transformer::Error::MissingArgument(format!("value for {}", option)))
+        };
+        match arg.as_str() {
+            "--schema" => arguments.schema = true,
+            "--profile" => arguments.sources.profile = Some(value("--profile")?),
+            "--set" => arguments.sources.sets.push(value("--set")?),
+            "--only" => arguments
+                .only
+                .extend(value("--only")?.split(',').map(str::to_string)),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() >= 2 && positional[0] == "config" && positional[1] == "show" {
+        arguments.show = true;
+        positional.drain(..2);
     }
-    for sale in &sales_and_products.sales {
-        conn.execute(
-            "INSERT INTO Sales (
-            id, product_id, sale_date, quantity, unit
-            ) VALUES ($1, $2, $3, $4, $5)",
-            params![
-                sale.id,
-                sale.product_id,
-                sale.date,
-                sale.quantity,
-                sale.unit,
-            ],
-        )?;
+    arguments.sources.config_file = positional.pop();
+    if !positional.is_empty() {
+        return Err(transformer::Error::Usage(format!(
+            "unexpected {:?}",
+            positional[0]
+        )));
     }
-    Ok(())
+    Ok(arguments)
 }
 
-fn recreate_postgresql_db(
-    postgresql_config: &Postgresql,
-) -> Result<postgres::Client, postgres::error::Error> {
-    use postgres::{Client, NoTls};
-    let mut conn = Client::connect(
-        &format!(
-            "postgres://{}{}{}@{}{}{}{}{}",
-            postgresql_config.username,
-            if postgresql_config.password.is_empty() {
-                ""
-            } else {
-                ":"
-            },
-            postgresql_config.password,
-            postgresql_config.host,
-            if postgresql_config.port.is_empty() {
-                ""
-            } else {
-                ":"
-            },
-            postgresql_config.port,
-            if postgresql_config.database.is_empty() {
-                ""
-            } else {
-                "/"
-            },
-            postgresql_config.database
-        ),
-        NoTls,
-    )?;
-    conn.execute("DROP TABLE Sales", &[])?;
-    conn.execute("DROP TABLE Products", &[])?;
-    conn.execute(
-        "CREATE TABLE Products (
-        id INTEGER PRIMARY KEY,
-        category TEXT NOT NULL,
-        name TEXT NOT NULL UNIQUE)",
-        &[],
-    )?;
-    conn.execute(
-        "CREATE TABLE Sales (
-        id TEXT PRIMARY KEY,
-        product_id INTEGER NOT NULL REFERENCES Products,
-        sale_date BIGINT NOT NULL,
-        quantity DOUBLE PRECISION NOT NULL,
-        unit TEXT NOT NULL)",
-        &[],
-    )?;
-    Ok(conn)
+fn fail(e: impl Into<Error>) -> ! {
+    let e = e.into();
+    eprintln!("transformer_complete: error: {}", e);
+    if matches!(&e, Error::Transformer(e) if e.is_usage()) {
+        eprintln!("\n{}", USAGE);
+        std::process::exit(2);
+    }
+    std::process::exit(1);
 }
 
-fn write_into_postgresql_db(
-    conn: &mut postgres::Client,
-    sales_and_products: &SalesAndProducts,
-) -> Result<(), postgres::error::Error> {
-    for product in &sales_and_products.products {
-        conn.execute(
-            "INSERT INTO Products (
-            id, category, name
-            ) VALUES ($1, $2, $3)",
-            &[&product.id, &product.category, &product.name],
-        )?;
-    }
-    for sale in &sales_and_products.sales {
-        conn.execute(
-            "INSERT INTO Sales (
-            id, product_id, sale_date, quantity, unit
-            ) VALUES ($1, $2, $3, $4, $5)",
-            &[
-                &sale.id,
-                &sale.product_id,
-                &sale.date,
-                &sale.quantity,
-                &sale.unit,
-            ],
-        )?;
+fn main() {
+    let arguments = parse_arguments(std::env::args().skip(1)).unwrap_or_else(|e| fail(e));
+    if arguments.schema {
+        let schema = schema::document_schema::<Config>("Config");
+        println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+        return;
     }
-    Ok(())
-}
 
-fn write_into_redis_store(
-    conn: &mut redis::Connection,
-    sales_and_products: &SalesAndProducts,
-) -> redis::RedisResult<()> {
-    for product in &sales_and_products.products {
-        conn.set(
-            format!("product:{}:category", product.id),
-            &product.category,
-        )?;
-        conn.set(format!("product:{}:name", product.id), &product.name)?;
+    // Define the config structure from the layered settings, once they are
+    // known to match the schema.
+    let layers =
+        config::load(&arguments.sources, &Config::json_schema()).unwrap_or_else(|e| fail(e));
+    let violations = schema::check(&Config::json_schema(), layers.document());
+    if !violations.is_empty() {
+        for violation in &violations {
+            eprintln!("{}", violation);
+        }
+        eprintln!(
+            "{} violations of the configuration schema.",
+            violations.len()
+        );
+        std::process::exit(1);
     }
-    for sale in &sales_and_products.sales {
-        conn.set(format!("sale:{}:product_id", sale.id), sale.product_id)?;
-        conn.set(format!("sale:{}:sale_date", sale.id), sale.date)?;
-        conn.set(format!("sale:{}:quantity", sale.id), sale.quantity)?;
-        conn.set(format!("sale:{}:unit", sale.id), &sale.unit)?;
+    let config: Config = serde_json::from_value(layers.document().clone())
+        .unwrap_or_else(|e| fail(transformer::Error::Config(e.to_string())));
+    if arguments.show {
+        let settings = serde_json::to_value(&config)
+            .unwrap_or_else(|e| fail(transformer::Error::Config(e.to_string())));
+        print!("{}", config::show(&layers, &settings));
+        return;
     }
-    Ok(())
-}
 
-fn print_row_count_in_sqlite_db(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
-    use rusqlite::params;
-    for count in conn
-        .prepare("SELECT COUNT(*) FROM Products")?
-        .query_map(params![], |row| {
-            let c: i64 = row.get(0)?;
-            Ok(c)
-        })?
-    {
-        if let Ok(count) = count {
-            println!("SQLite #Products={}. ", count);
+    let sinks = sink_registry(&config)
+        .select(&arguments.only)
+        .unwrap_or_else(|e| fail(e));
+    // A password written in a config file is only as secret as the file.
+    let passwords: [&[&str]; 2] = [
+        &["postgresql", "password"],
+        &["input", "postgresql", "password"],
+    ];
+    for path in passwords {
+        if let Some(file) = layers.file_of(path) {
+            secret::check_private(file).unwrap_or_else(|e| fail(e));
         }
     }
-    for count in conn
-        .prepare("SELECT COUNT(*) FROM Sales")?
-        .query_map(params![], |row| {
-            let c: i64 = row.get(0)?;
-            Ok(c)
-        })?
+
+    // Check the whole input before touching any database, so that a bad
+    // record cannot leave them half loaded.
+    let registry = Registry::new(&config.units).unwrap_or_else(|e| fail(e));
+    let mut source = open_source(&config).unwrap_or_else(|e| fail(e));
+    let violations = match source
+        .records()
+        .and_then(|records| Ok(validate_records(records, &config.validation, &registry)?))
     {
-        if let Ok(item) = count {
-            println!("SQLite #Sales={}. ", item);
+        Ok(violations) => violations,
+        Err(Error::Transformer(transformer::Error::Violations(violations))) => violations,
+        Err(e) => fail(e),
+    };
+    if !violations.is_empty() {
+        for violation in &violations {
+            eprintln!("{}", violation);
         }
+        eprintln!(
+            "{}, nothing written.",
+            transformer::Error::Violations(violations)
+        );
+        std::process::exit(1);
     }
-    Ok(())
-}
 
-fn print_row_count_in_postgresql_db(
-    conn: &mut postgres::Client,
-) -> Result<(), postgres::error::Error> {
-    for row in &conn.query("SELECT COUNT(*) FROM Products", &[])? {
-        let count: i64 = row.get(0);
-        println!("PostgreSQL #Products={}. ", count);
+    // A sink failing does not keep the others from being loaded.
+    let mut failures = 0;
+    for entry in &sinks {
+        let started = Instant::now();
+        let result = entry
+            .open()
+            .and_then(|mut sink| sinks::load(sink.as_mut(), source.records()?));
+        match result {
+            Ok(loaded) => {
+                println!("{} #Products={}. ", entry.label, loaded.counts.products);
+                println!("{} #Sales={}. ", entry.label, loaded.counts.sales);
+                let seconds = started.elapsed().as_secs_f64();
+                println!(
+                    "{}: loaded {} rows in {:.2} s, {:.0} rows/s.",
+                    entry.label,
+                    loaded.rows,
+                    seconds,
+                    loaded.rows as f64 / seconds
+                );
+            }
+            Err(e) => {
+                eprintln!("{}: failed: {}", entry.label, e);
+                failures += 1;
+            }
+        }
     }
-    for row in &conn.query("SELECT COUNT(*) FROM Sales", &[])? {
-        let count: i64 = row.get(0);
-        println!("PostgreSQL #Sales={}. ", count);
+    if failures > 0 {
+        eprintln!("{} of {} sinks failed.", failures, sinks.len());
+        std::process::exit(1);
     }
-    Ok(())
-}
-
-fn open_redis_store(redis_config: &Redis) -> redis::RedisResult<redis::Connection> {
-    Ok(
-        redis::Client::open(format!("redis://{}/", redis_config.host).as_str())?
-            .get_connection()?,
-    )
-}
-
-fn main() {
-    // Define the config structure by reading the TOML file
-    // specified in the command line.
-    let config: Config = {
-        let config_path = std::env::args().nth(1).unwrap();
-        let config_text = std::fs::read_to_string(&config_path).unwrap();
-        toml::from_str(&config_text).unwrap()
-    };
-
-    let mut sales_and_products = read_json_file(&config.input.json_file);
-
-    let sqlite_conn = recreate_sqlite_db(&config.sqlite).unwrap();
-    write_into_sqlite_db(&sqlite_conn, &sales_and_products).unwrap();
-
-    let mut postgresql_conn = recreate_postgresql_db(&config.postgresql).unwrap();
-    write_into_postgresql_db(&mut postgresql_conn, &sales_and_products).unwrap();
-
-    let mut redis_conn = open_redis_store(&config.redis).unwrap();
-    write_into_redis_store(&mut redis_conn, &sales_and_products).unwrap();
-
-    print_row_count_in_sqlite_db(&sqlite_conn).unwrap();
-    print_row_count_in_postgresql_db(&mut postgresql_conn).unwrap();
 }
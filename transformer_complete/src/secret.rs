@@ -0,0 +1,165 @@
+use std::fmt;
+use std::process::{Command, Stdio};
+
+use serde::{Serialize, Serializer};
+use serde_derive::Deserialize;
+use transformer::Error;
+
+const REDACTED: &str = "<redacted>";
+
+/// A setting such as a password, kept out of `Debug` output and of the
+/// settings `config show` prints.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+// Secrets are read as a single line, whatever ends it.
+fn from_line(text: &str) -> Secret {
+    Secret(text.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Reads a secret from a file, which only its owner and group may read.
+pub fn from_file(pathname: &str) -> Result<Secret, Error> {
+    check_private(pathname)?;
+    std::fs::read_to_string(pathname)
+        .map(|text| from_line(&text))
+        .map_err(|e| Error::from(e).in_file(pathname))
+}
+
+pub fn from_env(name: &str) -> Result<Secret, Error> {
+    std::env::var(name)
+        .map(Secret)
+        .map_err(|_| Error::Config(format!("environment variable {} is not set", name)))
+}
+
+/// Runs `command` with the shell and takes what it prints as the secret, so
+/// that it can come from a password manager or a vault.
+pub fn from_command(command: &str) -> Result<Secret, Error> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Config(format!(
+            "`{}` failed with {}",
+            command, output.status
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map(|text| from_line(&text))
+        .map_err(|_| Error::Config(format!("`{}` printed something not UTF-8", command)))
+}
+
+/// Fails if a file holding a secret can be read by anyone.
+pub fn check_private(pathname: &str) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = std::fs::metadata(pathname).map_err(|e| Error::from(e).in_file(pathname))?;
+        if metadata.permissions().mode() & 0o004 != 0 {
+            return Err(Error::Config(format!(
+                "{} holds a password but anyone can read it; run `chmod o-r {}`",
+                pathname, pathname
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A file of its own for each test and run, removed afterwards.
+    struct SecretFile(String);
+
+    impl SecretFile {
+        fn new(test: &str, text: &str, mode: u32) -> Self {
+            let name = format!("transformer_{}_{}.secret", std::process::id(), test);
+            let pathname = std::env::temp_dir().join(name).display().to_string();
+            std::fs::write(&pathname, text).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                let permissions = std::fs::Permissions::from_mode(mode);
+                std::fs::set_permissions(&pathname, permissions).unwrap();
+            }
+            #[cfg(not(unix))]
+            let _ = mode;
+            SecretFile(pathname)
+        }
+    }
+
+    impl Drop for SecretFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn is_redacted_when_printed() {
+        let secret = from_line("hunter2\n");
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"<redacted>\"");
+        let secret: Secret = serde_json::from_str("\"hunter2\"").unwrap();
+        assert_eq!(secret.expose(), "hunter2");
+    }
+
+    #[test]
+    fn reads_a_private_file_as_one_line() {
+        let file = SecretFile::new("private", "hunter2\r\n", 0o640);
+        assert_eq!(from_file(&file.0).unwrap().expose(), "hunter2");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_a_file_anyone_can_read() {
+        let file = SecretFile::new("public", "hunter2\n", 0o644);
+        match from_file(&file.0) {
+            Err(Error::Config(message)) => assert!(message.contains("chmod o-r")),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        let missing = format!("{}.missing", file.0);
+        let error = check_private(&missing).unwrap_err();
+        assert_eq!(
+            error
+                .location()
+                .and_then(|location| location.file.as_deref()),
+            Some(missing.as_str())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn takes_what_a_command_prints() {
+        assert_eq!(from_command("echo hunter2").unwrap().expose(), "hunter2");
+        assert!(matches!(from_command("exit 3"), Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,240 @@
+pub mod postgresql;
+pub mod redis;
+pub mod sqlite;
+
+use std::collections::HashSet;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use transformer::formats::Records;
+use transformer::schema::JsonSchema;
+use transformer::{Product, Record, Sale};
+
+use crate::error::Error;
+
+/// The sinks there are, by the name of their section, in the order they
+/// are loaded.
+pub const NAMES: [&str; 3] = ["sqlite", "postgresql", "redis"];
+
+/// A store records are loaded into. A new one needs only this implemented,
+/// its section in `Config` and its registration in the registry.
+pub trait Sink {
+    /// Makes the store ready to take the records, removing any from an
+    /// earlier load.
+    fn prepare(&mut self) -> Result<(), Error>;
+
+    fn write_product(&mut self, product: &Product) -> Result<(), Error>;
+
+    fn write_sale(&mut self, sale: &Sale) -> Result<(), Error>;
+
+    /// Makes sure all that was written is stored.
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The records in the store.
+    fn count(&mut self) -> Result<Counts, Error>;
+}
+
+/// What becomes of the records a database sink already holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The tables are dropped and created anew, so that they hold the input
+    /// only.
+    Recreate,
+    /// The tables are created if missing, records whose id is already there
+    /// are updated, and others are inserted. Records not in the input stay,
+    /// unless `delete_absent` is set.
+    Upsert,
+}
+
+impl JsonSchema for Mode {
+    fn json_schema() -> Value {
+        json!({ "type": "string", "enum": ["recreate", "upsert"] })
+    }
+}
+
+/// The ids of the records written, so that the others can be deleted.
+#[derive(Debug, Default)]
+pub struct Written {
+    pub products: HashSet<i32>,
+    pub sales: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub products: i64,
+    pub sales: i64,
+}
+
+/// What a load did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Loaded {
+    /// The records written by this load, inserted or updated.
+    pub rows: u64,
+    /// The records in the store after it, which may hold others in upsert
+    /// mode.
+    pub counts: Counts,
+}
+
+/// Loads `records` into `sink`, in place of what it held or over it, as its
+/// mode has it.
+pub fn load(sink: &mut dyn Sink, records: Records) -> Result<Loaded, Error> {
+    sink.prepare()?;
+    let mut rows = 0;
+    for record in records {
+        match record? {
+            Record::Product(product) => sink.write_product(&product)?,
+            Record::Sale(sale) => sink.write_sale(&sale)?,
+        }
+        rows += 1;
+    }
+    sink.finish()?;
+    Ok(Loaded {
+        rows,
+        counts: sink.count()?,
+    })
+}
+
+type Open<'a> = Box<dyn Fn() -> Result<Box<dyn Sink>, Error> + 'a>;
+
+/// A configured sink, not connected to until it is opened.
+pub struct Entry<'a> {
+    pub name: &'static str,
+    pub label: &'static str,
+    /// Whether it is loaded unless sinks are chosen on the command line.
+    pub enabled: bool,
+    open: Open<'a>,
+}
+
+impl Entry<'_> {
+    pub fn open(&self) -> Result<Box<dyn Sink>, Error> {
+        (self.open)()
+    }
+}
+
+/// The configured sinks, in the order they are loaded.
+#[derive(Default)]
+pub struct Registry<'a> {
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Registry<'a> {
+    pub fn add(
+        &mut self,
+        name: &'static str,
+        label: &'static str,
+        enabled: bool,
+        open: impl Fn() -> Result<Box<dyn Sink>, Error> + 'a,
+    ) {
+        self.entries.push(Entry {
+            name,
+            label,
+            enabled,
+            open: Box::new(open),
+        });
+    }
+
+    /// The sinks named by `only`, which may be disabled, or else the enabled
+    /// ones.
+    pub fn select(self, only: &[String]) -> Result<Vec<Entry<'a>>, transformer::Error> {
+        if let Some(name) = only.iter().find(|name| !NAMES.contains(&name.as_str())) {
+            return Err(transformer::Error::Usage(format!(
+                "unknown sink {:?}, expected one of {}",
+                name,
+                NAMES.join(", ")
+            )));
+        }
+        if let Some(name) = only
+            .iter()
+            .find(|name| !self.entries.iter().any(|entry| entry.name == *name))
+        {
+            return Err(transformer::Error::Config(format!(
+                "--only {} needs a [{}] section",
+                name, name
+            )));
+        }
+        let entries: Vec<Entry> = self
+            .entries
+            .into_iter()
+            .filter(|entry| {
+                if only.is_empty() {
+                    entry.enabled
+                } else {
+                    only.iter().any(|name| name == entry.name)
+                }
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(transformer::Error::Config(
+                "no sink is enabled; give a [sqlite], [postgresql] or [redis] section".to_string(),
+            ));
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SQLite enabled and PostgreSQL not, with no Redis section.
+    fn registry() -> Registry<'static> {
+        let mut registry = Registry::default();
+        let unopened = || -> Result<Box<dyn Sink>, Error> { unreachable!("not opened") };
+        registry.add("sqlite", "SQLite", true, unopened);
+        registry.add("postgresql", "PostgreSQL", false, unopened);
+        registry
+    }
+
+    fn selected(only: &[&str]) -> Result<Vec<&'static str>, transformer::Error> {
+        let only: Vec<String> = only.iter().map(|name| name.to_string()).collect();
+        Ok(registry()
+            .select(&only)?
+            .iter()
+            .map(|entry| entry.name)
+            .collect())
+    }
+
+    #[test]
+    fn selects_the_enabled_sinks_unless_told_which() {
+        assert_eq!(selected(&[]).unwrap(), ["sqlite"]);
+        assert_eq!(selected(&["postgresql"]).unwrap(), ["postgresql"]);
+        assert_eq!(
+            selected(&["postgresql", "sqlite"]).unwrap(),
+            ["sqlite", "postgresql"]
+        );
+    }
+
+    #[test]
+    fn rejects_sinks_unknown_or_not_configured() {
+        let error = selected(&["mysql"]).unwrap_err();
+        assert!(error.is_usage());
+        assert_eq!(
+            error.to_string(),
+            "unknown sink \"mysql\", expected one of sqlite, postgresql, redis"
+        );
+        match selected(&["redis"]) {
+            Err(transformer::Error::Config(message)) => {
+                assert_eq!(message, "--only redis needs a [redis] section")
+            }
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fails_when_no_sink_is_enabled() {
+        let mut registry = Registry::default();
+        registry.add(
+            "redis",
+            "Redis",
+            false,
+            || -> Result<Box<dyn Sink>, Error> { unreachable!("not opened") },
+        );
+        assert!(matches!(
+            registry.select(&[]),
+            Err(transformer::Error::Config(_))
+        ));
+    }
+}
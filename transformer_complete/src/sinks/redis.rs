@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+
+use redis::Commands;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use transformer::schema::{boolean, section, string, JsonSchema};
+use transformer::{Product, Sale};
+
+use super::{Counts, Sink};
+use crate::error::Error;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Redis {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub server: Server,
+}
+
+impl JsonSchema for Redis {
+    fn json_schema() -> Value {
+        let mut schema = Server::json_schema();
+        schema["properties"]["enabled"] = boolean();
+        schema
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Server {
+    pub host: String,
+}
+
+impl Server {
+    pub fn connect(&self) -> Result<redis::Connection, Error> {
+        let client = redis::Client::open(format!("redis://{}/", self.host).as_str())?;
+        Ok(client.get_connection()?)
+    }
+}
+
+impl JsonSchema for Server {
+    fn json_schema() -> Value {
+        section(json!({ "host": string() }), &["host"])
+    }
+}
+
+/// Keeps each field of a record under a key of its own, such as
+/// `product:1:name` and `sale:a-1:quantity`.
+pub struct RedisSink {
+    conn: redis::Connection,
+}
+
+impl RedisSink {
+    pub fn open(server: &Server) -> Result<Self, Error> {
+        Ok(RedisSink {
+            conn: server.connect()?,
+        })
+    }
+
+    // Walks the keys with SCAN rather than KEYS, which blocks the server
+    // until it has gone through all of them. SCAN may return a key more than
+    // once, so they are gathered in a set.
+    fn keys(&mut self, pattern: &str) -> Result<HashSet<String>, Error> {
+        Ok(self.conn.scan_match(pattern)?.collect())
+    }
+}
+
+impl Sink for RedisSink {
+    // Removes the records of an earlier load, as the databases drop their
+    // tables.
+    fn prepare(&mut self) -> Result<(), Error> {
+        let mut keys = self.keys("product:*")?;
+        keys.extend(self.keys("sale:*")?);
+        if !keys.is_empty() {
+            self.conn
+                .del::<_, ()>(keys.into_iter().collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
+
+    fn write_product(&mut self, product: &Product) -> Result<(), Error> {
+        self.conn.set::<_, _, ()>(
+            format!("product:{}:category", product.id),
+            &product.category,
+        )?;
+        self.conn
+            .set::<_, _, ()>(format!("product:{}:name", product.id), &product.name)?;
+        Ok(())
+    }
+
+    fn write_sale(&mut self, sale: &Sale) -> Result<(), Error> {
+        self.conn
+            .set::<_, _, ()>(format!("sale:{}:product_id", sale.id), sale.product_id)?;
+        self.conn
+            .set::<_, _, ()>(format!("sale:{}:sale_date", sale.id), sale.date)?;
+        self.conn
+            .set::<_, _, ()>(format!("sale:{}:quantity", sale.id), sale.quantity)?;
+        self.conn
+            .set::<_, _, ()>(format!("sale:{}:unit", sale.id), &sale.unit)?;
+        Ok(())
+    }
+
+    fn count(&mut self) -> Result<Counts, Error> {
+        Ok(Counts {
+            products: self.keys("product:*:name")?.len() as i64,
+            sales: self.keys("sale:*:unit")?.len() as i64,
+        })
+    }
+}
@@ -0,0 +1,420 @@
+use rusqlite::{params, Connection};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use transformer::schema::{boolean, section, string, JsonSchema};
+use transformer::{Product, Sale};
+
+use super::{Counts, Mode, Sink, Written};
+use crate::error::Error;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Sqlite {
+    pub enabled: bool,
+    pub db_file: String,
+    /// The records written between commits, or 0 to load them all in one
+    /// transaction, which a failure then undoes entirely. Otherwise, batches
+    /// committed before a failure stay.
+    pub batch_size: u64,
+    pub mode: Mode,
+    /// Deletes the records the input does not have, when upserting.
+    pub delete_absent: bool,
+}
+
+impl JsonSchema for Sqlite {
+    fn json_schema() -> Value {
+        section(
+            json!({
+                "enabled": boolean(),
+                "db_file": string(),
+                "batch_size": { "type": "integer", "minimum": 0 },
+                "mode": Mode::json_schema(),
+                "delete_absent": boolean(),
+            }),
+            &["db_file"],
+        )
+    }
+}
+
+// Recreated tables take each id once, so that a repeated one fails the load.
+const INSERT_PRODUCT: &str = "INSERT INTO Products (id, category, name) VALUES ($1, $2, $3)";
+
+const UPSERT_PRODUCT: &str = "INSERT INTO Products (
+    id, category, name
+    ) VALUES ($1, $2, $3)
+    ON CONFLICT (id) DO UPDATE SET
+    category = excluded.category, name = excluded.name";
+
+const INSERT_SALE: &str = "INSERT INTO Sales (
+    id, product_id, sale_date, quantity, unit
+    ) VALUES ($1, $2, $3, $4, $5)";
+
+const UPSERT_SALE: &str = "INSERT INTO Sales (
+    id, product_id, sale_date, quantity, unit
+    ) VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (id) DO UPDATE SET
+    product_id = excluded.product_id, sale_date = excluded.sale_date,
+    quantity = excluded.quantity, unit = excluded.unit";
+
+/// Loads the records in a transaction, with statements prepared once.
+pub struct SqliteSink {
+    conn: Connection,
+    batch_size: u64,
+    mode: Mode,
+    // Records written since the last commit.
+    uncommitted: u64,
+    // Kept only when records not written are to be deleted.
+    written: Option<Written>,
+}
+
+impl SqliteSink {
+    pub fn open(config: &Sqlite) -> Result<Self, Error> {
+        Ok(SqliteSink {
+            conn: Connection::open(&config.db_file)?,
+            batch_size: config.batch_size,
+            mode: config.mode,
+            uncommitted: 0,
+            written: (config.mode == Mode::Upsert && config.delete_absent).then(Written::default),
+        })
+    }
+
+    fn written_one(&mut self) -> Result<(), Error> {
+        self.uncommitted += 1;
+        if self.batch_size > 0 && self.uncommitted >= self.batch_size {
+            self.conn.execute_batch("COMMIT; BEGIN")?;
+            self.uncommitted = 0;
+        }
+        Ok(())
+    }
+
+    fn delete_absent(&mut self, written: &Written) -> Result<(), Error> {
+        let sales: Vec<String> = self
+            .conn
+            .prepare("SELECT id FROM Sales")?
+            .query_map(params![], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for id in sales.iter().filter(|id| !written.sales.contains(*id)) {
+            self.conn
+                .prepare_cached("DELETE FROM Sales WHERE id = $1")?
+                .execute(params![id])?;
+        }
+        let products: Vec<i32> = self
+            .conn
+            .prepare("SELECT id FROM Products")?
+            .query_map(params![], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for id in products.iter().filter(|id| !written.products.contains(*id)) {
+            self.conn
+                .prepare_cached("DELETE FROM Products WHERE id = $1")?
+                .execute(params![id])?;
+        }
+        Ok(())
+    }
+}
+
+// A transaction left open, as when loading failed, is rolled back.
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        if !self.conn.is_autocommit() {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+impl Sink for SqliteSink {
+    fn prepare(&mut self) -> Result<(), Error> {
+        // Dropping the tables is part of the transaction, so that they are
+        // back as they were if loading fails.
+        self.conn.execute_batch("BEGIN")?;
+        if self.mode == Mode::Recreate {
+            self.conn.execute("DROP TABLE IF EXISTS Sales", params![])?;
+            self.conn
+                .execute("DROP TABLE IF EXISTS Products", params![])?;
+        }
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS Products (
+                id INTEGER PRIMARY KEY,
+                category TEXT NOT NULL,
+                name TEXT NOT NULL UNIQUE)",
+            params![],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS Sales (
+                id TEXT PRIMARY KEY,
+                product_id INTEGER NOT NULL REFERENCES Products,
+                sale_date BIGINT NOT NULL,
+                quantity DOUBLE PRECISION NOT NULL,
+                unit TEXT NOT NULL)",
+            params![],
+        )?;
+        Ok(())
+    }
+
+    fn write_product(&mut self, product: &Product) -> Result<(), Error> {
+        let sql = match self.mode {
+            Mode::Recreate => INSERT_PRODUCT,
+            Mode::Upsert => UPSERT_PRODUCT,
+        };
+        self.conn.prepare_cached(sql)?.execute(params![
+            product.id,
+            product.category,
+            product.name
+        ])?;
+        if let Some(written) = &mut self.written {
+            written.products.insert(product.id);
+        }
+        self.written_one()
+    }
+
+    fn write_sale(&mut self, sale: &Sale) -> Result<(), Error> {
+        let sql = match self.mode {
+            Mode::Recreate => INSERT_SALE,
+            Mode::Upsert => UPSERT_SALE,
+        };
+        self.conn.prepare_cached(sql)?.execute(params![
+            sale.id,
+            sale.product_id,
+            sale.date,
+            sale.quantity,
+            sale.unit,
+        ])?;
+        if let Some(written) = &mut self.written {
+            written.sales.insert(sale.id.clone());
+        }
+        self.written_one()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        if let Some(written) = self.written.take() {
+            self.delete_absent(&written)?;
+        }
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    fn count(&mut self) -> Result<Counts, Error> {
+        let count = |table: &str| {
+            self.conn.query_row(
+                &format!("SELECT COUNT(*) FROM {}", table),
+                params![],
+                |row| row.get(0),
+            )
+        };
+        Ok(Counts {
+            products: count("Products")?,
+            sales: count("Sales")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::types::FromSql;
+    use transformer::Record;
+
+    use transformer::formats::Records;
+
+    use super::*;
+    use crate::sinks::load;
+
+    fn product(id: i32, name: &str) -> Record {
+        Record::Product(Product {
+            id,
+            category: "fruit".to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    fn sale(id: &str, product_id: i32, quantity: f64) -> Record {
+        Record::Sale(Sale {
+            id: id.to_string(),
+            product_id,
+            date: 1_234_567_890,
+            quantity,
+            unit: "kg".to_string(),
+        })
+    }
+
+    fn load_into(sink: &mut SqliteSink, records: Vec<Record>) -> Counts {
+        load(sink, Box::new(records.into_iter().map(Ok)))
+            .unwrap()
+            .counts
+    }
+
+    fn sink(db_file: &str, mode: Mode, delete_absent: bool) -> SqliteSink {
+        batched_sink(db_file, mode, delete_absent, 0)
+    }
+
+    fn batched_sink(db_file: &str, mode: Mode, delete_absent: bool, batch_size: u64) -> SqliteSink {
+        SqliteSink::open(&Sqlite {
+            enabled: true,
+            db_file: db_file.to_string(),
+            batch_size,
+            mode,
+            delete_absent,
+        })
+        .unwrap()
+    }
+
+    // A database file of its own for each test and run, removed afterwards.
+    struct DbFile(String);
+
+    impl DbFile {
+        fn new(test: &str) -> Self {
+            let name = format!("transformer_{}_{}.sqlite", std::process::id(), test);
+            DbFile(std::env::temp_dir().join(name).display().to_string())
+        }
+    }
+
+    impl Drop for DbFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    // Records, then an error as when the input turns out to be broken.
+    fn failing(records: Vec<Record>) -> Records {
+        let broken = transformer::Error::Config("broken input".to_string());
+        Box::new(records.into_iter().map(Ok).chain(Some(Err(broken))))
+    }
+
+    fn pairs<T: FromSql, U: FromSql>(sink: &SqliteSink, sql: &str) -> Vec<(T, U)> {
+        sink.conn
+            .prepare(sql)
+            .unwrap()
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn products(sink: &SqliteSink) -> Vec<(i32, String)> {
+        pairs(sink, "SELECT id, name FROM Products ORDER BY id")
+    }
+
+    fn sales(sink: &SqliteSink) -> Vec<(String, f64)> {
+        pairs(sink, "SELECT id, quantity FROM Sales ORDER BY id")
+    }
+
+    fn first_load() -> Vec<Record> {
+        vec![
+            product(1, "apple"),
+            product(2, "pear"),
+            sale("a", 1, 1.0),
+            sale("b", 2, 2.0),
+        ]
+    }
+
+    fn second_load() -> Vec<Record> {
+        vec![
+            product(1, "green apple"),
+            product(3, "plum"),
+            sale("a", 1, 5.0),
+            sale("c", 3, 3.0),
+            sale("c", 3, 4.0),
+        ]
+    }
+
+    #[test]
+    fn recreate_fails_on_a_repeated_id() {
+        let records = vec![product(1, "apple"), product(1, "pear")];
+        let mut recreate = sink(":memory:", Mode::Recreate, false);
+        assert!(load(&mut recreate, Box::new(records.into_iter().map(Ok))).is_err());
+        let records = vec![product(1, "apple"), sale("a", 1, 1.0), sale("a", 1, 2.0)];
+        let mut recreate = sink(":memory:", Mode::Recreate, false);
+        assert!(load(&mut recreate, Box::new(records.into_iter().map(Ok))).is_err());
+    }
+
+    #[test]
+    fn upsert_updates_and_inserts_and_keeps_the_rest() {
+        let db_file = DbFile::new("upsert");
+        let db_file = db_file.0.as_str();
+        load_into(&mut sink(db_file, Mode::Recreate, false), first_load());
+        let mut upsert = sink(db_file, Mode::Upsert, false);
+        let counts = load_into(&mut upsert, second_load());
+        assert_eq!(
+            counts,
+            Counts {
+                products: 3,
+                sales: 3
+            }
+        );
+        assert_eq!(
+            products(&upsert),
+            [
+                (1, "green apple".to_string()),
+                (2, "pear".to_string()),
+                (3, "plum".to_string())
+            ]
+        );
+        assert_eq!(
+            sales(&upsert),
+            [
+                ("a".to_string(), 5.0),
+                ("b".to_string(), 2.0),
+                ("c".to_string(), 4.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn upsert_deletes_absent_records_when_asked() {
+        let db_file = DbFile::new("delete_absent");
+        let db_file = db_file.0.as_str();
+        load_into(&mut sink(db_file, Mode::Recreate, false), first_load());
+        let mut upsert = sink(db_file, Mode::Upsert, true);
+        load_into(&mut upsert, second_load());
+        assert_eq!(
+            products(&upsert),
+            [(1, "green apple".to_string()), (3, "plum".to_string())]
+        );
+        assert_eq!(
+            sales(&upsert),
+            [("a".to_string(), 5.0), ("c".to_string(), 4.0)]
+        );
+    }
+
+    #[test]
+    fn failure_keeps_committed_batches_only() {
+        let db_file = DbFile::new("batches");
+        let db_file = db_file.0.as_str();
+        let records = vec![
+            product(1, "apple"),
+            product(2, "pear"),
+            sale("a", 1, 1.0),
+            sale("b", 2, 2.0),
+            sale("c", 2, 3.0),
+        ];
+        let mut batched = batched_sink(db_file, Mode::Recreate, false, 2);
+        assert!(load(&mut batched, failing(records)).is_err());
+        drop(batched);
+        // The fifth record was not committed, and is rolled back.
+        let reopened = sink(db_file, Mode::Recreate, false);
+        assert_eq!(
+            products(&reopened),
+            [(1, "apple".to_string()), (2, "pear".to_string())]
+        );
+        assert_eq!(
+            sales(&reopened),
+            [("a".to_string(), 1.0), ("b".to_string(), 2.0)]
+        );
+    }
+
+    #[test]
+    fn failure_in_one_transaction_leaves_the_tables_as_they_were() {
+        let db_file = DbFile::new("one_transaction");
+        let db_file = db_file.0.as_str();
+        load_into(&mut sink(db_file, Mode::Recreate, false), first_load());
+        let mut recreate = sink(db_file, Mode::Recreate, false);
+        assert!(load(&mut recreate, failing(second_load())).is_err());
+        drop(recreate);
+        let reopened = sink(db_file, Mode::Recreate, false);
+        assert_eq!(
+            products(&reopened),
+            [(1, "apple".to_string()), (2, "pear".to_string())]
+        );
+        assert_eq!(
+            sales(&reopened),
+            [("a".to_string(), 1.0), ("b".to_string(), 2.0)]
+        );
+    }
+}
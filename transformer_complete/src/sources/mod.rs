@@ -0,0 +1,48 @@
+pub mod postgresql;
+pub mod redis;
+pub mod sqlite;
+
+use transformer::formats::{read_records, Format, FormatOptions, Records};
+use transformer::SalesAndProducts;
+
+use crate::error::Error;
+
+/// Where records are read from. Every call to `records` reads them anew,
+/// so that a source can feed one sink after another.
+pub trait Source {
+    /// The records, products before sales.
+    fn records(&mut self) -> Result<Records, Error>;
+}
+
+/// A JSON, XML or CSV file, read one record at a time.
+pub struct FileSource {
+    format: Format,
+    pathname: String,
+    options: FormatOptions,
+}
+
+impl FileSource {
+    /// The file is checked against the schema the first time it is read, if
+    /// `options` say so; later reads only repeat that one.
+    pub fn new(format: Format, pathname: &str, options: FormatOptions) -> Self {
+        FileSource {
+            format,
+            pathname: pathname.to_string(),
+            options,
+        }
+    }
+}
+
+impl Source for FileSource {
+    fn records(&mut self) -> Result<Records, Error> {
+        let records = read_records(self.format, &self.pathname, &self.options)?;
+        self.options.check_schema = false;
+        Ok(records)
+    }
+}
+
+// Stores are read whole, as records cannot be streamed from them without
+// holding on to a connection.
+fn into_records(data: SalesAndProducts) -> Records {
+    Box::new(data.into_iter().map(Ok))
+}
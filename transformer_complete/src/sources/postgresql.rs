@@ -0,0 +1,55 @@
+use postgres::Client;
+use transformer::formats::Records;
+use transformer::{Product, Sale, SalesAndProducts};
+
+use super::{into_records, Source};
+use crate::error::Error;
+use crate::sinks::postgresql::Server;
+
+/// The tables a PostgreSQL sink writes.
+pub struct PostgresqlSource {
+    client: Client,
+}
+
+impl PostgresqlSource {
+    pub fn open(server: &Server) -> Result<Self, Error> {
+        Ok(PostgresqlSource {
+            client: server.connect()?,
+        })
+    }
+}
+
+impl Source for PostgresqlSource {
+    fn records(&mut self) -> Result<Records, Error> {
+        let products = self
+            .client
+            .query("SELECT id, category, name FROM Products ORDER BY id", &[])?
+            .iter()
+            .map(|row| {
+                Ok(Product {
+                    id: row.try_get(0)?,
+                    category: row.try_get(1)?,
+                    name: row.try_get(2)?,
+                })
+            })
+            .collect::<Result<_, postgres::Error>>()?;
+        let sales = self
+            .client
+            .query(
+                "SELECT id, product_id, sale_date, quantity, unit FROM Sales ORDER BY id",
+                &[],
+            )?
+            .iter()
+            .map(|row| {
+                Ok(Sale {
+                    id: row.try_get(0)?,
+                    product_id: row.try_get(1)?,
+                    date: row.try_get(2)?,
+                    quantity: row.try_get(3)?,
+                    unit: row.try_get(4)?,
+                })
+            })
+            .collect::<Result<_, postgres::Error>>()?;
+        Ok(into_records(SalesAndProducts { products, sales }))
+    }
+}
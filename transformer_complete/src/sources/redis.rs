@@ -0,0 +1,64 @@
+use redis::Commands;
+use transformer::formats::Records;
+use transformer::{Location, Product, Sale, SalesAndProducts};
+
+use super::{into_records, Source};
+use crate::error::Error;
+use crate::sinks::redis::Server;
+
+/// The keys a Redis sink writes, such as `product:1:name`.
+pub struct RedisSource {
+    conn: redis::Connection,
+}
+
+impl RedisSource {
+    pub fn open(server: &Server) -> Result<Self, Error> {
+        Ok(RedisSource {
+            conn: server.connect()?,
+        })
+    }
+
+    // The ids of the records with a `field` key, in order.
+    fn ids(&mut self, record: &str, field: &str) -> Result<Vec<String>, Error> {
+        let prefix = format!("{}:", record);
+        let suffix = format!(":{}", field);
+        let keys: Vec<String> = self.conn.keys(format!("{}*{}", prefix, suffix))?;
+        let mut ids: Vec<String> = keys
+            .iter()
+            .filter_map(|key| key.strip_prefix(&prefix)?.strip_suffix(&suffix))
+            .map(str::to_string)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+impl Source for RedisSource {
+    fn records(&mut self) -> Result<Records, Error> {
+        let mut products = Vec::new();
+        for id in self.ids("product", "name")? {
+            products.push(Product {
+                id: id.parse().map_err(|_| transformer::Error::InvalidValue {
+                    location: Location::default(),
+                    field: "id",
+                    value: id.clone(),
+                    reason: "not a number".to_string(),
+                })?,
+                category: self.conn.get(format!("product:{}:category", id))?,
+                name: self.conn.get(format!("product:{}:name", id))?,
+            });
+        }
+        products.sort_by_key(|product| product.id);
+        let mut sales = Vec::new();
+        for id in self.ids("sale", "unit")? {
+            sales.push(Sale {
+                product_id: self.conn.get(format!("sale:{}:product_id", id))?,
+                date: self.conn.get(format!("sale:{}:sale_date", id))?,
+                quantity: self.conn.get(format!("sale:{}:quantity", id))?,
+                unit: self.conn.get(format!("sale:{}:unit", id))?,
+                id,
+            });
+        }
+        Ok(into_records(SalesAndProducts { products, sales }))
+    }
+}
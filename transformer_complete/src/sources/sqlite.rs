@@ -0,0 +1,49 @@
+use rusqlite::{params, Connection, OpenFlags};
+use transformer::formats::Records;
+use transformer::{Product, Sale, SalesAndProducts};
+
+use super::{into_records, Source};
+use crate::error::Error;
+
+/// The tables an SQLite sink writes, in an existing database file.
+pub struct SqliteSource {
+    conn: Connection,
+}
+
+impl SqliteSource {
+    pub fn open(db_file: &str) -> Result<Self, Error> {
+        Ok(SqliteSource {
+            conn: Connection::open_with_flags(db_file, OpenFlags::SQLITE_OPEN_READ_ONLY)?,
+        })
+    }
+}
+
+impl Source for SqliteSource {
+    fn records(&mut self) -> Result<Records, Error> {
+        let products = self
+            .conn
+            .prepare("SELECT id, category, name FROM Products ORDER BY id")?
+            .query_map(params![], |row| {
+                Ok(Product {
+                    id: row.get(0)?,
+                    category: row.get(1)?,
+                    name: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        let sales = self
+            .conn
+            .prepare("SELECT id, product_id, sale_date, quantity, unit FROM Sales ORDER BY id")?
+            .query_map(params![], |row| {
+                Ok(Sale {
+                    id: row.get(0)?,
+                    product_id: row.get(1)?,
+                    date: row.get(2)?,
+                    quantity: row.get(3)?,
+                    unit: row.get(4)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(into_records(SalesAndProducts { products, sales }))
+    }
+}